@@ -1,9 +1,35 @@
+use crate::intern::Symbol;
+use crate::parser::{Ident, Spanned, TopLevelDecl};
 
+/*
+* The root of the tree: everything the parser gets out of one source file.
+* The nodes below the top level declarations live next to the code that parses
+* them in parser.rs.
+*/
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    pub package: Ident,
+    // every spec of every import declaration, in order, groups are flattened
+    pub imports: Vec<ImportSpec>,
+    pub decls: Vec<Spanned<TopLevelDecl>>,
+}
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportSpec {
+    pub name: ImportName,
+    // the decoded import path, "fmt" or "github.com/x/y"
+    pub path: Spanned<Symbol>,
+}
 
-
-
-pub struct AST {
-    pub type: NodeType,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportName {
+    // import "fmt", the package is known by its own name
+    Default,
+    // import f "fmt"
+    Alias(Ident),
+    // import . "fmt", exported names are used without a qualifier
+    Dot,
+    // import _ "fmt", only imported for its side effects
+    Blank,
 }
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+/*
+* Identifiers and literal text get interned: every distinct string is stored exactly once
+* and everything else just holds a Symbol, which is a u32 index into that table. Tokens and
+* AST nodes become cheap to copy, and comparing two names is comparing two integers instead
+* of two strings. The strings are never freed, they live for as long as the compiler runs,
+* which is what lets Symbol::as_str hand out a &'static str. There is one table for the
+* whole process, so a Symbol means the same thing on every thread.
+*
+* String literals can hold bytes that aren't UTF-8 ("\xff"), so what actually gets
+* interned is bytes. as_str gives a lossy UTF-8 version of those for display, as_bytes
+* the real thing.
+*/
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+struct Interner {
+    map: HashMap<&'static [u8], Symbol>,
+    bytes: Vec<&'static [u8]>,
+    // the same memory as `bytes`, unless they weren't valid UTF-8
+    strings: Vec<&'static str>,
+}
+
+static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+
+fn interner() -> MutexGuard<'static, Interner> {
+    let interner = INTERNER.get_or_init(|| Mutex::new(Interner::new()));
+    // nothing in here can panic halfway through an update, a poisoned lock is still usable
+    interner.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+impl Interner {
+    fn new() -> Interner {
+        let mut interner = Interner {
+            map: HashMap::new(),
+            bytes: Vec::new(),
+            strings: Vec::new(),
+        };
+        // the empty string is always Symbol(0), see Symbol::EMPTY
+        interner.intern("");
+        interner
+    }
+    fn intern(&mut self, s: &str) -> Symbol {
+        self.intern_bytes(s.as_bytes())
+    }
+    fn intern_bytes(&mut self, b: &[u8]) -> Symbol {
+        if let Some(&sym) = self.map.get(b) {
+            return sym;
+        }
+        let b: &'static [u8] = Box::leak(b.to_vec().into_boxed_slice());
+        let s: &'static str = match std::str::from_utf8(b) {
+            Ok(s) => s,
+            Err(_) => Box::leak(String::from_utf8_lossy(b).into_owned().into_boxed_str()),
+        };
+        let sym = Symbol(self.bytes.len() as u32);
+        self.bytes.push(b);
+        self.strings.push(s);
+        self.map.insert(b, sym);
+        sym
+    }
+}
+
+impl Symbol {
+    // used for tokens that don't carry any text, like punctuation
+    pub const EMPTY: Symbol = Symbol(0);
+
+    pub fn intern(s: &str) -> Symbol {
+        interner().intern(s)
+    }
+    pub fn intern_bytes(b: &[u8]) -> Symbol {
+        interner().intern_bytes(b)
+    }
+    // invalid UTF-8 comes out as U+FFFD, use as_bytes where that matters
+    pub fn as_str(&self) -> &'static str {
+        interner().strings[self.0 as usize]
+    }
+    pub fn as_bytes(&self) -> &'static [u8] {
+        interner().bytes[self.0 as usize]
+    }
+    pub fn is_empty(&self) -> bool {
+        *self == Symbol::EMPTY
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+// show the string rather than the index, otherwise AST dumps are unreadable
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes = self.as_bytes();
+        match std::str::from_utf8(bytes) {
+            Ok(s) => write!(f, "Symbol({:?})", s),
+            Err(_) => write!(f, "Symbol(b\"{}\")", bytes.escape_ascii()),
+        }
+    }
+}
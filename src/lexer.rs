@@ -1,6 +1,9 @@
+use crate::intern::Symbol;
+use crate::source_map::SourceFile;
 use std::collections::HashMap;
-use std::iter::Peekable;
-use std::str::Chars;
+use std::fmt;
+use std::sync::OnceLock;
+use unicode_general_category::{get_general_category, GeneralCategory};
 /*
 * My initial plan was to do this iteratively, and I am definitely still
 * going to make use of Rust's Iterator<Peekable> methods. But I think I am
@@ -20,275 +23,962 @@ use std::str::Chars;
 
 #[derive(Debug, Clone)]
 pub struct Lexer {
-    pub input: i32,
-    pub reader: Peekable<Chars<'static>>,
+    pub input: String,
     pub curr_token: Token,
+    // positions are byte offsets into the SourceMap, so they start at `base`
+    // (the file's start_pos) rather than 0
     pub position: u32,
+    base: u32,
+    // every problem found so far, lexing carries on past all of them
+    pub errors: Vec<LexError>,
+    // offsets of characters that were invalid UTF-8 in the original file,
+    // in reverse so the next one to report is at the end
+    invalid_utf8: Vec<u32>,
+    // set when the last token can end a statement, see skip_whitespace
+    insert_semi: bool,
+    // set once the Iterator has handed out EOF
+    finished: bool,
+    // lossless mode, whitespace and comments are kept as Trivia on the tokens
+    keep_trivia: bool,
+    // trivia we've skipped over that is waiting for the next token
+    pending: Vec<Trivia>,
 }
+
+// Every reserved word in the language maps to its own TokenType, anything else
+// that looks like a word is an identifier. The table never changes, so it is
+// built the first time it's needed and shared by every Lexer.
+fn keywords() -> &'static HashMap<&'static str, TokenType> {
+    static KEYWORDS: OnceLock<HashMap<&'static str, TokenType>> = OnceLock::new();
+    KEYWORDS.get_or_init(|| {
+        HashMap::from([
+            ("break", TokenType::Break),
+            ("case", TokenType::Case),
+            ("chan", TokenType::Chan),
+            ("const", TokenType::Const),
+            ("continue", TokenType::Continue),
+            ("default", TokenType::Default),
+            ("defer", TokenType::Defer),
+            ("else", TokenType::Else),
+            ("fallthrough", TokenType::Fallthrough),
+            ("for", TokenType::For),
+            ("func", TokenType::Func),
+            ("go", TokenType::Go),
+            ("goto", TokenType::Goto),
+            ("if", TokenType::If),
+            ("import", TokenType::Import),
+            ("interface", TokenType::Interface),
+            ("map", TokenType::Map),
+            ("package", TokenType::Package),
+            ("range", TokenType::Range),
+            ("return", TokenType::Return),
+            ("select", TokenType::Select),
+            ("struct", TokenType::Struct),
+            ("switch", TokenType::Switch),
+            ("type", TokenType::Type),
+            ("var", TokenType::Var),
+        ])
+    })
+}
+
 impl Lexer {
-    pub fn peek_token(&self) -> Option<char, String> {
-        if self.input.len() <= self.position.try_into().unwrap() {
-            Some('\0')
-        } else {
+    pub fn new(file: String) -> Lexer {
+        Lexer {
+            input: file,
+            curr_token: Token::from(TokenType::EOF),
+            position: 0,
+            base: 0,
+            errors: Vec::new(),
+            invalid_utf8: Vec::new(),
+            insert_semi: false,
+            finished: false,
+            keep_trivia: false,
+            pending: Vec::new(),
+        }
+    }
+    // lexes a file registered in a SourceMap, so spans point back into that file
+    pub fn for_file(file: &SourceFile) -> Lexer {
+        let mut lexer = Lexer::new(file.src.clone());
+        lexer.position = file.start_pos;
+        lexer.base = file.start_pos;
+        lexer.invalid_utf8 = file
+            .invalid_utf8
+            .iter()
+            .rev()
+            .map(|pos| pos + file.start_pos)
+            .collect();
+        lexer
+    }
+    // Like for_file, but nothing in the source is thrown away: every TS carries the
+    // whitespace and comments around it, so the tokens can be printed back out to
+    // exactly the original file. This is what a formatter or language server wants.
+    pub fn lossless(file: &SourceFile) -> Lexer {
+        let mut lexer = Lexer::for_file(file);
+        lexer.keep_trivia = true;
+        lexer
+    }
+    fn rest(&self) -> &str {
+        &self.input[(self.position - self.base) as usize..]
+    }
+    // look at the character under the cursor without consuming it
+    pub fn peek_char(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+    // one character past the cursor, needed for things like '//' and '/*'
+    pub fn peek_next(&self) -> Option<char> {
+        self.rest().chars().nth(1)
+    }
+    // characters can be more than one byte, so always move through the
+    // input with this rather than bumping position by hand
+    pub fn next_char(&mut self) -> Option<char> {
+        let ch = self.peek_char()?;
+        self.position += ch.len_utf8() as u32;
+        Some(ch)
+    }
+    // consumes the next character only if it is `ch`, this is how we get maximal
+    // munch on operators like `<<=` or `&^`
+    fn eat(&mut self, ch: char) -> bool {
+        if self.peek_char() == Some(ch) {
             self.position += 1;
-            return *self.reader.peek();
+            true
+        } else {
+            false
         }
     }
-    pub fn new(&self, file: String) -> Lexer {
-        let readr: Peekable<Chars> = file.chars().peekable().clone();
-        let token: Token = self.parse_token();
-        return Lexer {
-            input: file,
-            reader: readr,
-            curr_token: token,
-            position: 0,
-        };
+    // Skips whitespace and comments, then parses exactly one token and wraps it
+    // with the Span it was read from. Once the input is exhausted this keeps
+    // returning EOF. Problems are pushed onto `errors` and we keep going.
+    pub fn next_token(&mut self) -> TS {
+        if let Some(pos) = self.skip_whitespace() {
+            // Go's automatic semicolon, it takes up no room in the source
+            // so the newline itself is still skipped on the next call
+            self.insert_semi = false;
+            self.curr_token = Token::synthetic_semicolon();
+            return TS::new(self.curr_token, Span { beg: pos, end: pos });
+        }
+        let beg = self.position;
+        let token = self.parse_token();
+        self.report_invalid_utf8();
+        self.insert_semi = token.kind.ends_statement();
+        self.curr_token = token;
+        let mut ts = TS::new(
+            token,
+            Span {
+                beg,
+                end: self.position,
+            },
+        );
+        if self.keep_trivia {
+            ts.leading = std::mem::take(&mut self.pending);
+            if ts.token.kind.starts_declaration() {
+                mark_doc_comments(&mut ts.leading);
+            }
+            ts.trailing = self.skip_trailing();
+        }
+        ts
     }
-    pub fn parse_token(&self) -> Option<Token> {
-        let token: Token = Token::new();
-        let ch = self.peek_token().unwrap();
-        if ch == '"' {
-            return Some(Token {
-                kind: TokenType::Quote,
-                value: "none".to_string(),
-            });
-        } else if ch == '\'' {
-            return Some(Token {
-                kind: TokenType::SingleQuote,
-                value: "none".to_string(),
+    fn error(&mut self, kind: LexErrorKind, beg: u32, end: u32) {
+        self.errors.push(LexError {
+            kind,
+            span: Span { beg, end },
+        });
+    }
+    // Bad UTF-8 was swapped for U+FFFD when the file was loaded, once we have
+    // moved past one of those (in a token, string or comment) report it.
+    fn report_invalid_utf8(&mut self) {
+        while let Some(&pos) = self.invalid_utf8.last() {
+            if pos >= self.position {
+                break;
+            }
+            self.invalid_utf8.pop();
+            let end = pos + char::REPLACEMENT_CHARACTER.len_utf8() as u32;
+            self.error(LexErrorKind::InvalidUtf8, pos, end);
+        }
+    }
+    // Skips whitespace and comments. If the last token could end a statement and
+    // we run into the end of the line (or a block comment spanning lines, or EOF)
+    // we stop and return the position a semicolon should be inserted at.
+    // In lossless mode everything skipped is saved up as leading trivia for the next token.
+    fn skip_whitespace(&mut self) -> Option<u32> {
+        loop {
+            let beg = self.position;
+            match (self.peek_char(), self.peek_next()) {
+                (Some('\n'), _) | (None, _) if self.insert_semi => return Some(self.position),
+                (Some('\n'), _) => {
+                    self.position += 1;
+                    self.push_trivia(TriviaKind::Newline, beg);
+                }
+                // Go only counts these four as whitespace
+                (Some(' ' | '\t' | '\r'), _) => self.skip_spaces(),
+                (Some('/'), Some('/')) => self.line_comment(),
+                (Some('/'), Some('*')) => {
+                    if self.block_comment() && self.insert_semi {
+                        return Some(beg);
+                    }
+                }
+                _ => return None,
+            }
+        }
+    }
+    // Trivia that follows a token on the same line belongs to that token. We stop
+    // before the newline (and before a block comment running onto the next line)
+    // so skip_whitespace still gets to insert a semicolon there.
+    fn skip_trailing(&mut self) -> Vec<Trivia> {
+        loop {
+            match (self.peek_char(), self.peek_next()) {
+                (Some(' ' | '\t' | '\r'), _) => self.skip_spaces(),
+                (Some('/'), Some('/')) => self.line_comment(),
+                (Some('/'), Some('*')) => match self.rest()[2..].find("*/") {
+                    Some(end) if !self.rest()[..end + 2].contains('\n') => {
+                        self.block_comment();
+                    }
+                    _ => break,
+                },
+                _ => break,
+            }
+        }
+        std::mem::take(&mut self.pending)
+    }
+    fn push_trivia(&mut self, kind: TriviaKind, beg: u32) {
+        if self.keep_trivia {
+            self.pending.push(Trivia {
+                kind,
+                span: Span {
+                    beg,
+                    end: self.position,
+                },
             });
-        } else if ch.is_whitespace() {
-            // Skip whitespace characters
-            return None;
-        } else if ch == '\t' {
-            return None;
-        } else if ch == '\n' {
+        }
+    }
+    fn skip_spaces(&mut self) {
+        let beg = self.position;
+        while let Some(' ' | '\t' | '\r') = self.peek_char() {
             self.position += 1;
-            return None;
         }
+        self.push_trivia(TriviaKind::Whitespace, beg);
+    }
+    //inline comment, leave the newline so it can end the statement
+    fn line_comment(&mut self) {
+        let beg = self.position;
+        while let Some(next) = self.peek_char() {
+            if next == '\n' {
+                break;
+            }
+            self.next_char();
+        }
+        self.push_trivia(TriviaKind::LineComment, beg);
+    }
+    // block comment, ignore all words/chars until we see these again.
+    // Returns true if the comment spans more than one line.
+    fn block_comment(&mut self) -> bool {
+        let beg = self.position;
+        let mut newline = false;
+        let mut terminated = false;
+        self.position += 2;
+        while let Some(next) = self.next_char() {
+            if next == '*' && self.peek_char() == Some('/') {
+                self.position += 1;
+                terminated = true;
+                break;
+            }
+            newline |= next == '\n';
+        }
+        if !terminated {
+            self.error(LexErrorKind::UnterminatedComment, beg, self.position);
+        }
+        self.push_trivia(TriviaKind::BlockComment, beg);
+        newline
+    }
+    pub fn parse_token(&mut self) -> Token {
+        let ch = match self.next_char() {
+            Some(ch) => ch,
+            None => return Token::from(TokenType::EOF),
+        };
         match ch {
-            '(' => {
-                return Some(Token {
-                    kind: TokenType::OpenParen,
-                    value: "none".to_string(),
-                })
-            }
-            ')' => {
-                return Some(Token {
-                    kind: TokenType::ClosedParen,
-                    value: "none".to_string(),
-                })
-            }
-            '{' => {
-                return Some(Token {
-                    kind: TokenType::OpenBracket,
-                    value: "none".to_string(),
-                })
-            }
-            '}' => {
-                return Some(Token {
-                    kind: TokenType::ClosedBracket,
-                    value: "none".to_string(),
-                })
-            }
-            '|' => {
-                if self.peek_token() == "|" {
-                    return Some(Token {
-                        kind: TokenType::Or,
-                        value: "Or".to_string(),
-                    });
+            '"' => self.parse_string(),
+            '`' => self.parse_raw_string(),
+            '\'' => self.parse_rune(),
+            '(' => Token::from(TokenType::OpenParen),
+            ')' => Token::from(TokenType::ClosedParen),
+            '{' => Token::from(TokenType::OpenBracket),
+            '}' => Token::from(TokenType::ClosedBracket),
+            '[' => Token::from(TokenType::OpenSquare),
+            ']' => Token::from(TokenType::ClosedSquare),
+            '|' => Token::from(if self.eat('|') {
+                TokenType::OrOr
+            } else if self.eat('=') {
+                TokenType::OrAssign
+            } else {
+                TokenType::Or
+            }),
+            '*' => Token::from(if self.eat('=') {
+                TokenType::StarAssign
+            } else {
+                TokenType::Star
+            }),
+            '!' => Token::from(if self.eat('=') {
+                TokenType::NotEqual
+            } else {
+                TokenType::Bang
+            }),
+            '&' => Token::from(if self.eat('&') {
+                TokenType::AndAnd
+            } else if self.eat('^') {
+                if self.eat('=') {
+                    TokenType::BitClearAssign
                 } else {
-                    return Some(Token {
-                        kind: TokenType::Or,
-                        value: "Logical Or".to_string(),
-                    });
+                    TokenType::BitClear
                 }
-            }
-            '\\' => {
-                return Some(Token {
-                    kind: TokenType::BackSlash,
-                    value: "none".to_string(),
-                })
-            }
-            '*' => {
-                return Some(Token {
-                    kind: TokenType::Star,
-                    value: "none".to_string(),
-                })
-            }
-            '!' => {
-                return Some(Token {
-                    kind: TokenType::Bang,
-                    value: "none".to_string(),
-                })
-            }
-            '&' => {
-                if self.peek_token() == "&" {
-                    return Some(Token {
-                        kind: TokenType::AndAnd,
-                        value: "logical and".to_string(),
-                    });
+            } else if self.eat('=') {
+                TokenType::AndAssign
+            } else {
+                TokenType::And
+            }),
+            '%' => Token::from(if self.eat('=') {
+                TokenType::ModuloAssign
+            } else {
+                TokenType::Modulo
+            }),
+            '^' => Token::from(if self.eat('=') {
+                TokenType::CaretAssign
+            } else {
+                TokenType::Caret
+            }),
+            '+' => Token::from(if self.eat('+') {
+                TokenType::Increment
+            } else if self.eat('=') {
+                TokenType::PlusAssign
+            } else {
+                TokenType::Plus
+            }),
+            ',' => Token::from(TokenType::Comma),
+            '.' => match (self.peek_char(), self.peek_next()) {
+                (Some('0'..='9'), _) => self.parse_number(ch),
+                (Some('.'), Some('.')) => {
+                    self.position += 2;
+                    Token::from(TokenType::Ellipsis)
+                }
+                _ => Token::from(TokenType::Period),
+            },
+            '-' => Token::from(if self.eat('-') {
+                TokenType::Decrement
+            } else if self.eat('=') {
+                TokenType::MinusAssign
+            } else {
+                TokenType::Minus
+            }),
+            '=' => Token::from(if self.eat('=') {
+                TokenType::Equals
+            } else {
+                TokenType::Assign
+            }),
+            ':' => Token::from(if self.eat('=') {
+                TokenType::ColonAssign
+            } else {
+                TokenType::Colon
+            }),
+            ';' => Token::from(TokenType::Semicolon),
+            '<' => Token::from(if self.eat('<') {
+                if self.eat('=') {
+                    TokenType::LshiftAssign
+                } else {
+                    TokenType::Lshift
+                }
+            } else if self.eat('=') {
+                TokenType::LessThanOrEqual
+            } else if self.eat('-') {
+                TokenType::Arrow
+            } else {
+                TokenType::LeftCaret
+            }),
+            '>' => Token::from(if self.eat('>') {
+                if self.eat('=') {
+                    TokenType::RshiftAssign
                 } else {
-                    return Some(Token {
-                        kind: TokenType::And,
-                        value: "".to_string(),
-                    });
+                    TokenType::Rshift
+                }
+            } else if self.eat('=') {
+                TokenType::GreaterThanOrEqual
+            } else {
+                TokenType::RightCaret
+            }),
+            '/' => Token::from(if self.eat('=') {
+                TokenType::SlashAssign
+            } else {
+                TokenType::FwdSlash
+            }),
+            // the tilde of a type term like ~int
+            '~' => Token::new(TokenType::Operand, intern_char(ch)),
+            '0'..='9' => self.parse_number(ch),
+            ch if is_letter(ch) => {
+                let beg = self.position - ch.len_utf8() as u32;
+                while let Some(ch) = self.peek_char() {
+                    if !is_letter(ch) && !is_digit(ch) {
+                        break;
+                    }
+                    self.next_char();
+                }
+                // the word is interned straight out of the input, no String needed
+                let word =
+                    &self.input[(beg - self.base) as usize..(self.position - self.base) as usize];
+                if word == "_" {
+                    return Token::new(TokenType::Blank, Symbol::intern(word));
+                }
+                // keywords keep their text as the value so they still print nicely
+                match keywords().get(word) {
+                    Some(&kind) => Token::new(kind, Symbol::intern(word)),
+                    None => Token::new(TokenType::Ident, Symbol::intern(word)),
                 }
             }
-            '%' => {
-                return Some(Token {
-                    kind: TokenType::Modulo,
-                    value: "none".to_string(),
-                })
-            }
-            '^' => {
-                return Some(Token {
-                    kind: TokenType::Caret,
-                    value: "none".to_string(),
-                })
-            }
-            '+' => {
-                return Some(Token {
-                    kind: TokenType::Plus,
-                    value: "none".to_string(),
-                })
-            }
-            ',' => {
-                return Some(Token {
-                    kind: TokenType::Comma,
-                    value: "none".to_string(),
-                })
-            }
-            '.' => {
-                return Some(Token {
-                    kind: TokenType::Period,
-                    value: "none".to_string(),
-                })
-            }
-            '_' => {
-                return Some(Token {
-                    kind: TokenType::Underscore,
-                    value: "none".to_string(),
-                })
-            }
-            '-' => {
-                return Some(Token {
-                    kind: TokenType::Minus,
-                    value: "none".to_string(),
-                })
-            }
-            '#' => {
-                return Some(Token {
-                    kind: TokenType::Hashtag,
-                    value: "none".to_string(),
-                })
-            }
-            '=' => {
-                return Some(Token {
-                    kind: TokenType::Equals,
-                    value: "none".to_string(),
-                })
-            }
-            ':' => {
-                return Some(Token {
-                    kind: TokenType::Colon,
-                    value: "none".to_string(),
-                })
-            }
-            ';' => {
-                return Some(Token {
-                    kind: TokenType::Semicolon,
-                    value: "none".to_string(),
-                })
-            }
-            '<' => {
-                return Some(Token {
-                    kind: TokenType::LeftCarrot,
-                    value: "none".to_string(),
-                })
-            }
-            '>' => {
-                return Some(Token {
-                    kind: TokenType::RightCarrot,
-                    value: "none".to_string(),
-                })
-            }
-            '/' => {
-                if *self.reader.peek().unwrap() == '/' {
-                    //inline comment
-                    while let Some(&next) = self.next() {
-                        if next == '\n' {
-                            break;
-                        } else {
-                            continue;
-                        }
+            _ => {
+                // U+FFFD standing in for bad UTF-8 gets its own error in next_token
+                let pos = self.position - ch.len_utf8() as u32;
+                if !self.invalid_utf8.contains(&pos) {
+                    self.error(LexErrorKind::UnknownChar(ch), pos, self.position);
+                }
+                Token::new(TokenType::Invalid, intern_char(ch))
+            }
+        }
+    }
+    // Numeric literals follow the Go spec: decimal, 0x/0o/0b prefixed and legacy
+    // octal integers, decimal and hex floats, '_' digit separators, and an 'i'
+    // suffix for imaginary numbers. `first` is the already consumed first char,
+    // which is either a digit or the '.' of something like `.5`.
+    // The token value is the literal exactly as written, even if it is malformed.
+    fn parse_number(&mut self, first: char) -> Token {
+        let beg = self.position - 1;
+        let mut text = String::from(first);
+        let mut kind = TokenType::IntLiteral;
+        let mut radix = 10;
+        let mut prefix = '\0';
+        // bit 0 is set once we have seen a digit, bit 1 once we have seen a '_'
+        let mut digsep = 0;
+        // the first digit that is out of range for the radix, e.g. the 8 in 0o78
+        let mut invalid: Option<(u32, char)> = None;
+
+        if first != '.' {
+            if first == '0' {
+                match self.peek_char().map(|c| c.to_ascii_lowercase()) {
+                    Some(c @ ('x' | 'o' | 'b')) => {
+                        text.push(self.next_char().unwrap());
+                        radix = match c {
+                            'x' => 16,
+                            'o' => 8,
+                            _ => 2,
+                        };
+                        prefix = c;
                     }
-                } else if *self.reader.peek().unwrap() == '*' {
-                    // block comment, ignore all words/chars until we see these again
-                    while let Some(&next) = self.next() {
-                        if next == '*' && *self.peek_token() == '/' {
-                            self.peek_token();
-                            break;
-                        }
+                    _ => {
+                        // leading 0, which is an octal literal unless it turns into a float
+                        radix = 8;
+                        prefix = '0';
+                        digsep = 1;
                     }
-                } else {
-                    return Some(Token {
-                        kind: TokenType::ForwardSlash,
-                        value: "none".to_string(),
-                    });
                 }
+            } else {
+                digsep = 1;
             }
-            '?' | '@' | '$' | '~' => {
-                return Some(Token {
-                    kind: TokenType::Operand,
-                    value: String::from(ch),
-                })
-            }
-            '0'..='9' => {
-                let mut number = String::new();
-                number.push(ch);
-                while let Some(&ch) = self.parse_token() {
-                    if ch.is_ascii_digit() {
-                        number.push(ch);
-                    } else {
-                        break;
-                    }
+            digsep |= self.scan_digits(&mut text, radix, &mut invalid);
+        } else {
+            kind = TokenType::FloatLiteral;
+        }
+        // fractional part
+        if first == '.' || self.peek_char() == Some('.') {
+            if first != '.' {
+                text.push(self.next_char().unwrap());
+            }
+            kind = TokenType::FloatLiteral;
+            if prefix == 'o' || prefix == 'b' {
+                let msg = format!("invalid radix point in {}", literal_name(prefix));
+                self.error(LexErrorKind::MalformedNumber(msg), beg, self.position);
+            }
+            digsep |= self.scan_digits(&mut text, radix, &mut invalid);
+        }
+        if digsep & 1 == 0 {
+            let msg = format!("{} has no digits", literal_name(prefix));
+            self.error(LexErrorKind::MalformedNumber(msg), beg, self.position);
+        }
+        // exponent
+        match self.peek_char().map(|c| c.to_ascii_lowercase()) {
+            Some(e @ ('e' | 'p')) => {
+                let exp_beg = self.position;
+                if e == 'e' && prefix != '\0' && prefix != '0' {
+                    let msg = "'e' exponent requires decimal mantissa".to_string();
+                    self.error(LexErrorKind::MalformedNumber(msg), exp_beg, exp_beg + 1);
                 }
-                return Some(Token {
-                    kind: TokenType::NumberLiteral,
-                    value: number.clone(),
-                });
-            }
-            'a'..='z' | 'A'..='Z' => {
-                let mut word: String = String::new();
-                word.push(ch);
-                while let Some(&ch) = self.parse_token() {
-                    if ch.is_ascii_alphabetic() {
-                        word.push(ch);
-                    } else {
-                        break;
+                if e == 'p' && prefix != 'x' {
+                    let msg = "'p' exponent requires hexadecimal mantissa".to_string();
+                    self.error(LexErrorKind::MalformedNumber(msg), exp_beg, exp_beg + 1);
+                }
+                text.push(self.next_char().unwrap());
+                kind = TokenType::FloatLiteral;
+                if let Some(sign @ ('+' | '-')) = self.peek_char() {
+                    text.push(sign);
+                    self.position += 1;
+                }
+                let ds = self.scan_digits(&mut text, 10, &mut None);
+                digsep |= ds;
+                if ds & 1 == 0 {
+                    let msg = "exponent has no digits".to_string();
+                    self.error(LexErrorKind::MalformedNumber(msg), exp_beg, self.position);
+                }
+            }
+            _ if prefix == 'x' && kind == TokenType::FloatLiteral => {
+                let msg = "hexadecimal mantissa requires a 'p' exponent".to_string();
+                self.error(LexErrorKind::MalformedNumber(msg), beg, self.position);
+            }
+            _ => (),
+        }
+        // imaginary suffix
+        if self.peek_char() == Some('i') {
+            text.push(self.next_char().unwrap());
+            kind = TokenType::ImagLiteral;
+        }
+        if kind == TokenType::IntLiteral {
+            if let Some((pos, digit)) = invalid {
+                let msg = format!("invalid digit {:?} in {}", digit, literal_name(prefix));
+                self.error(LexErrorKind::MalformedNumber(msg), pos, pos + 1);
+            }
+        }
+        if digsep & 2 != 0 {
+            if let Some(i) = invalid_separator(&text) {
+                let pos = beg + i as u32;
+                let msg = "'_' must separate successive digits".to_string();
+                self.error(LexErrorKind::MalformedNumber(msg), pos, pos + 1);
+            }
+        }
+        Token::new(kind, Symbol::intern(&text))
+    }
+    // Eats digits and '_' separators for the given radix. Decimal digits are always
+    // consumed so that 0b102 is reported as a bad digit rather than two tokens.
+    // Returns the digsep bits described in parse_number.
+    fn scan_digits(
+        &mut self,
+        text: &mut String,
+        radix: u32,
+        invalid: &mut Option<(u32, char)>,
+    ) -> u8 {
+        let mut digsep = 0;
+        while let Some(ch) = self.peek_char() {
+            if ch == '_' {
+                digsep |= 2;
+            } else if ch.is_ascii_digit() || (radix == 16 && ch.is_ascii_hexdigit()) {
+                if !ch.is_digit(radix) && invalid.is_none() {
+                    *invalid = Some((self.position, ch));
+                }
+                digsep |= 1;
+            } else {
+                break;
+            }
+            text.push(ch);
+            self.position += 1;
+        }
+        digsep
+    }
+    // Interpreted string literal, the opening '"' has already been consumed.
+    // The token value is the decoded bytes, not the source text, and those need
+    // not be UTF-8 (see Symbol::as_bytes). An unterminated string stops at the
+    // end of the line so the next line still lexes normally.
+    fn parse_string(&mut self) -> Token {
+        let beg = self.position - 1;
+        // \x and octal escapes produce raw bytes, so decode into bytes first
+        let mut bytes: Vec<u8> = Vec::new();
+        loop {
+            match self.peek_char() {
+                Some('"') => {
+                    self.position += 1;
+                    break;
+                }
+                Some('\n') | None => {
+                    self.error(LexErrorKind::UnterminatedString, beg, self.position);
+                    break;
+                }
+                Some('\\') => {
+                    self.position += 1;
+                    self.parse_escape('"', &mut bytes);
+                }
+                Some(ch) => {
+                    self.next_char();
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+        Token::new(TokenType::StringLiteral, Symbol::intern_bytes(&bytes))
+    }
+    // Raw string literal between backticks. No escapes, may span multiple lines,
+    // carriage returns are dropped from the value like Go does.
+    fn parse_raw_string(&mut self) -> Token {
+        let beg = self.position - 1;
+        let mut value = String::new();
+        loop {
+            match self.next_char() {
+                Some('`') => break,
+                Some('\r') => (),
+                Some(ch) => value.push(ch),
+                None => {
+                    self.error(LexErrorKind::UnterminatedRawString, beg, self.position);
+                    break;
+                }
+            }
+        }
+        Token::new(TokenType::StringLiteral, Symbol::intern(&value))
+    }
+    // Rune literal, exactly one character or escape sequence between single quotes.
+    fn parse_rune(&mut self) -> Token {
+        let beg = self.position - 1;
+        let mut runes: Vec<char> = Vec::new();
+        let mut valid = true;
+        loop {
+            match self.peek_char() {
+                Some('\'') => {
+                    self.position += 1;
+                    break;
+                }
+                Some('\n') | None => {
+                    self.error(LexErrorKind::UnterminatedRune, beg, self.position);
+                    valid = false;
+                    break;
+                }
+                Some('\\') => {
+                    let esc_beg = self.position;
+                    self.position += 1;
+                    let mut bytes: Vec<u8> = Vec::new();
+                    if !self.parse_escape('\'', &mut bytes) {
+                        valid = false;
+                        continue;
+                    }
+                    // a \x or octal escape in a rune is a code point, not a byte
+                    let rune = match bytes.as_slice() {
+                        [byte] => Some(char::from(*byte)),
+                        _ => std::str::from_utf8(&bytes)
+                            .ok()
+                            .and_then(|s| s.chars().next()),
+                    };
+                    match rune {
+                        Some(rune) => runes.push(rune),
+                        None => {
+                            let kind = LexErrorKind::InvalidEscape(
+                                "invalid escape sequence in rune literal",
+                            );
+                            self.error(kind, esc_beg, self.position);
+                            valid = false;
+                        }
                     }
                 }
-                return Some(Token {
-                    kind: TokenType::StringLiteral,
-                    value: word.clone(),
-                });
+                Some(ch) => {
+                    self.next_char();
+                    runes.push(ch);
+                }
+            }
+        }
+        match runes.as_slice() {
+            [rune] => return Token::new(TokenType::RuneLiteral, intern_char(*rune)),
+            // an empty rune because of a bad escape has already been reported
+            [] if valid => {
+                let kind = LexErrorKind::InvalidRune("empty rune literal");
+                self.error(kind, beg, self.position);
+            }
+            [] => (),
+            _ => {
+                let kind = LexErrorKind::InvalidRune("more than one character in rune literal");
+                self.error(kind, beg, self.position);
+            }
+        }
+        Token::new(TokenType::RuneLiteral, Symbol::EMPTY)
+    }
+    // Decodes one escape sequence, the '\\' has already been consumed. `quote` is the
+    // delimiter of the surrounding literal, which is the only quote allowed to be escaped.
+    // A bad escape is reported and skipped, returning false.
+    fn parse_escape(&mut self, quote: char, bytes: &mut Vec<u8>) -> bool {
+        let beg = self.position - 1;
+        let ch = self.peek_char();
+        let simple = match ch {
+            Some('a') => Some(0x07),
+            Some('b') => Some(0x08),
+            Some('f') => Some(0x0c),
+            Some('n') => Some(b'\n'),
+            Some('r') => Some(b'\r'),
+            Some('t') => Some(b'\t'),
+            Some('v') => Some(0x0b),
+            Some('\\') => Some(b'\\'),
+            Some(c) if c == quote => Some(c as u8),
+            _ => None,
+        };
+        if let Some(byte) = simple {
+            self.position += 1;
+            bytes.push(byte);
+            return true;
+        }
+        let (digits, radix, max) = match ch {
+            Some('0'..='7') => (3, 8, 255),
+            Some('x') => {
+                self.position += 1;
+                (2, 16, 255)
+            }
+            Some('u') => {
+                self.position += 1;
+                (4, 16, char::MAX as u32)
+            }
+            Some('U') => {
+                self.position += 1;
+                (8, 16, char::MAX as u32)
+            }
+            // leave the end of the line alone so the literal reports itself unterminated
+            Some('\n') | None => {
+                let kind = LexErrorKind::InvalidEscape("escape sequence not terminated");
+                self.error(kind, beg, self.position);
+                return false;
+            }
+            Some(_) => {
+                self.next_char();
+                let kind = LexErrorKind::InvalidEscape("unknown escape sequence");
+                self.error(kind, beg, self.position);
+                return false;
+            }
+        };
+        let mut value: u32 = 0;
+        for _ in 0..digits {
+            match self.peek_char().and_then(|c| c.to_digit(radix)) {
+                Some(d) => {
+                    value = value * radix + d;
+                    self.position += 1;
+                }
+                None => {
+                    let kind = LexErrorKind::InvalidEscape("invalid character in escape sequence");
+                    self.error(kind, beg, self.position);
+                    return false;
+                }
+            }
+        }
+        if value > max {
+            let msg = if radix == 8 {
+                "octal escape value > 255"
+            } else {
+                "escape sequence is invalid Unicode code point"
+            };
+            self.error(LexErrorKind::InvalidEscape(msg), beg, self.position);
+            return false;
+        }
+        if radix == 16 && digits > 2 {
+            // \u and \U name a code point, surrogate halves are rejected by from_u32
+            match char::from_u32(value) {
+                Some(c) => {
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                None => {
+                    let kind = LexErrorKind::InvalidEscape(
+                        "escape sequence is invalid Unicode code point",
+                    );
+                    self.error(kind, beg, self.position);
+                    return false;
+                }
             }
-            _ => return None,
+        } else {
+            bytes.push(value as u8);
         }
+        true
     }
 }
 
+// Go identifiers are letters, digits and '_'. A letter is anything in the Unicode
+// categories Lu, Ll, Lt, Lm and Lo, and a digit anything in Nd. Rust's is_alphabetic
+// and is_numeric won't do: they also take letter numbers like Ⅻ, other numbers
+// like ² and combining marks.
+fn is_letter(ch: char) -> bool {
+    if ch.is_ascii() {
+        return ch == '_' || ch.is_ascii_alphabetic();
+    }
+    matches!(
+        get_general_category(ch),
+        GeneralCategory::UppercaseLetter
+            | GeneralCategory::LowercaseLetter
+            | GeneralCategory::TitlecaseLetter
+            | GeneralCategory::ModifierLetter
+            | GeneralCategory::OtherLetter
+    )
+}
+fn is_digit(ch: char) -> bool {
+    if ch.is_ascii() {
+        return ch.is_ascii_digit();
+    }
+    get_general_category(ch) == GeneralCategory::DecimalNumber
+}
+
+fn intern_char(ch: char) -> Symbol {
+    Symbol::intern(ch.encode_utf8(&mut [0; 4]))
+}
+
+fn literal_name(prefix: char) -> &'static str {
+    match prefix {
+        'x' => "hexadecimal literal",
+        'o' | '0' => "octal literal",
+        'b' => "binary literal",
+        _ => "decimal literal",
+    }
+}
+
+// Returns the index of the first '_' in a number literal that isn't sitting
+// between two digits (a base prefix like 0x counts as a digit), if any.
+fn invalid_separator(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut hex = false;
+    // '_' for a separator, '0' for a digit and '.' for anything else
+    let mut d = b'.';
+    let mut i = 0;
+    if bytes.len() >= 2 && bytes[0] == b'0' {
+        let x = bytes[1].to_ascii_lowercase();
+        if x == b'x' || x == b'o' || x == b'b' {
+            hex = x == b'x';
+            d = b'0';
+            i = 2;
+        }
+    }
+    while i < bytes.len() {
+        let p = d;
+        d = bytes[i];
+        if d == b'_' {
+            if p != b'0' {
+                return Some(i);
+            }
+        } else if d.is_ascii_digit() || (hex && d.is_ascii_hexdigit()) {
+            d = b'0';
+        } else {
+            if p == b'_' {
+                return Some(i - 1);
+            }
+            d = b'.';
+        }
+        i += 1;
+    }
+    if d == b'_' {
+        return Some(bytes.len() - 1);
+    }
+    None
+}
+
 #[derive(Debug, PartialEq, Clone, Eq)]
+pub enum LexErrorKind {
+    UnknownChar(char),
+    UnterminatedString,
+    UnterminatedRawString,
+    UnterminatedRune,
+    UnterminatedComment,
+    InvalidEscape(&'static str),
+    InvalidRune(&'static str),
+    MalformedNumber(String),
+    InvalidUtf8,
+}
+
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+}
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexErrorKind::UnknownChar(ch) => write!(f, "invalid character {:?}", ch),
+            LexErrorKind::UnterminatedString => write!(f, "string literal not terminated"),
+            LexErrorKind::UnterminatedRawString => {
+                write!(f, "raw string literal not terminated")
+            }
+            LexErrorKind::UnterminatedRune => write!(f, "rune literal not terminated"),
+            LexErrorKind::UnterminatedComment => write!(f, "comment not terminated"),
+            LexErrorKind::InvalidEscape(msg) => write!(f, "{}", msg),
+            LexErrorKind::InvalidRune(msg) => write!(f, "{}", msg),
+            LexErrorKind::MalformedNumber(msg) => write!(f, "{}", msg),
+            LexErrorKind::InvalidUtf8 => write!(f, "invalid UTF-8 encoding"),
+        }
+    }
+}
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}..{}", self.kind, self.span.beg, self.span.end)
+    }
+}
+
+// The Lexer is a lazy stream of tokens, which is what the Parser consumes. The
+// stream always finishes with exactly one EOF token, after that it is empty.
+impl Iterator for Lexer {
+    type Item = TS;
+
+    fn next(&mut self) -> Option<TS> {
+        if self.finished {
+            return None;
+        }
+        let ts = self.next_token();
+        self.finished = ts.token.kind == TokenType::EOF;
+        Some(ts)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Eq)]
 pub struct Token {
     pub kind: TokenType,
-    pub value: String,
+    // identifier name, keyword or decoded literal, empty for punctuation
+    pub value: Symbol,
+    // true for semicolons the lexer inserted at the end of a line
+    pub synthetic: bool,
 }
 
 #[derive(Debug, PartialEq, Clone, Eq)]
 pub struct TS {
     pub token: Token,
     pub span: Span,
+    // whitespace and comments before and after the token, only filled in
+    // when lexing losslessly. Trailing trivia stops at the end of the line.
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    LineComment,
+    BlockComment,
+    // a comment directly above a declaration, with no blank line in between
+    DocComment,
+}
+
+// Trivia only stores where it is, its text can be sliced out of the SourceFile
+#[derive(Debug, PartialEq, Clone, Copy, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+}
+
+impl TS {
+    pub fn new(token: Token, span: Span) -> TS {
+        TS {
+            token,
+            span,
+            leading: Vec::new(),
+            trailing: Vec::new(),
+        }
+    }
+}
+
+// Walks backwards from a declaration keyword, turning the comments above it into
+// doc comments until we hit a blank line or something that isn't a comment.
+fn mark_doc_comments(leading: &mut [Trivia]) {
+    let mut newlines = 0;
+    for trivia in leading.iter_mut().rev() {
+        match trivia.kind {
+            TriviaKind::Whitespace => (),
+            TriviaKind::Newline => {
+                newlines += 1;
+                if newlines > 1 {
+                    break;
+                }
+            }
+            TriviaKind::LineComment | TriviaKind::BlockComment | TriviaKind::DocComment => {
+                trivia.kind = TriviaKind::DocComment;
+                newlines = 0;
+            }
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Copy, Eq, Clone)]
@@ -297,20 +987,24 @@ pub struct Span {
     pub end: u32,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, PartialEq, Clone, Copy, Eq, Hash)]
 pub enum TokenType {
+    Ident,
     StringLiteral,
-    NumberLiteral,
+    RuneLiteral,
+    IntLiteral,
+    FloatLiteral,
+    ImagLiteral,
     Operand,
     Caret,
     Period,
     Colon,
     Semicolon,
-    Underscore,
-    Hashtag,
+    // the blank identifier `_`
+    Blank,
     Star,
     Modulo,
-    BackSlash,
     FwdSlash,
     Bang,
     Comma,
@@ -320,71 +1014,264 @@ pub enum TokenType {
     ClosedParen,
     OpenParen,
     OpenBracket,
+    ClosedBracket,
+    OpenSquare,
+    ClosedSquare,
+    Assign,
     Equals,
     Plus,
     Minus,
-    ClosedBracket,
-    Quote,
-    SingleQuote,
     And,
+    // something we couldn't lex, the error is in Lexer::errors
+    Invalid,
+
+    // multi character operators
+    ColonAssign,
+    NotEqual,
+    LessThanOrEqual,
+    GreaterThanOrEqual,
+    Lshift,
+    Rshift,
+    BitClear,
+    AndAnd,
+    OrOr,
+    Arrow,
+    Increment,
+    Decrement,
+    Ellipsis,
+    PlusAssign,
+    MinusAssign,
+    StarAssign,
+    SlashAssign,
+    ModuloAssign,
+    AndAssign,
+    OrAssign,
+    CaretAssign,
+    BitClearAssign,
+    LshiftAssign,
+    RshiftAssign,
+
+    // keywords
+    Break,
+    Case,
+    Chan,
+    Const,
+    Continue,
+    Default,
+    Defer,
+    Else,
+    Fallthrough,
+    For,
+    Func,
+    Go,
+    Goto,
+    If,
+    Import,
+    Interface,
+    Map,
+    Package,
+    Range,
+    Return,
+    Select,
+    Struct,
+    Switch,
+    Type,
+    Var,
+
     EOF,
 }
 impl Token {
-    pub fn new() -> Token {
-        return Token {
-            kind: TokenType::new(),
-            value: "".to_string(),
-        };
+    pub fn new(kind: TokenType, value: Symbol) -> Token {
+        Token {
+            kind,
+            value,
+            synthetic: false,
+        }
+    }
+    pub fn synthetic_semicolon() -> Token {
+        Token {
+            kind: TokenType::Semicolon,
+            value: Symbol::intern("\n"),
+            synthetic: true,
+        }
+    }
+}
+// punctuation carries no interesting value of its own
+impl From<TokenType> for Token {
+    fn from(kind: TokenType) -> Token {
+        Token::new(kind, Symbol::EMPTY)
     }
-    pub fn to_string(&self) -> String {
+}
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.kind {
-            TokenType::StringLiteral => {
-                return String::from(format!("StringLiteral: {}", self.value.clone()))
-            }
-            TokenType::NumberLiteral => {
-                return String::from(format!("NumberLiteral: {}", self.value.clone()))
-            }
-            _ => return self.kind.to_string(),
+            TokenType::Ident => write!(f, "Ident: {}", self.value),
+            TokenType::Invalid => write!(f, "Invalid: {:?}", self.value.as_str()),
+            TokenType::StringLiteral => write!(f, "StringLiteral: {:?}", self.value.as_str()),
+            TokenType::RuneLiteral => write!(f, "RuneLiteral: {:?}", self.value.as_str()),
+            TokenType::IntLiteral => write!(f, "IntLiteral: {}", self.value),
+            TokenType::FloatLiteral => write!(f, "FloatLiteral: {}", self.value),
+            TokenType::ImagLiteral => write!(f, "ImagLiteral: {}", self.value),
+            kind if kind.is_keyword() => write!(f, "Keyword: {}", self.value),
+            kind => write!(f, "{}", kind),
         }
     }
 }
 
 impl TokenType {
-    pub fn new() -> TokenType {
-        return TokenType::new();
+    // Go's rule for automatic semicolons: a newline after one of these tokens
+    // terminates the statement.
+    pub fn ends_statement(&self) -> bool {
+        matches!(
+            self,
+            TokenType::Ident
+                | TokenType::Blank
+                | TokenType::IntLiteral
+                | TokenType::FloatLiteral
+                | TokenType::ImagLiteral
+                | TokenType::RuneLiteral
+                | TokenType::StringLiteral
+                | TokenType::Break
+                | TokenType::Continue
+                | TokenType::Fallthrough
+                | TokenType::Return
+                | TokenType::Increment
+                | TokenType::Decrement
+                | TokenType::ClosedParen
+                | TokenType::ClosedSquare
+                | TokenType::ClosedBracket
+        )
     }
-    pub fn to_string(&self) -> String {
-        match self {
-            TokenType::Minus => return String::from("Minus/Dash"),
-            TokenType::Plus => return String::from("Plus"),
-            TokenType::Equals => return String::from("Equals"),
-            TokenType::StringLiteral => return String::from("String Literal"),
-            TokenType::NumberLiteral => return String::from("Number Literal"),
-            TokenType::Operand => return String::from("Operand/Symbol"),
-            TokenType::Quote => return String::from("DoubleQuote"),
-            TokenType::SingleQuote => return String::from("Single Quote"),
-            TokenType::Star => return String::from("Star/Asterisk"),
-            TokenType::Modulo => return String::from("Modulo"),
-            TokenType::Bang => return String::from("Bang"),
-            TokenType::BackSlash => return String::from("Backslash"),
-            TokenType::FwdSlash => return String::from("Forwardslash"),
-            TokenType::Semicolon => return String::from("Semi-Colon"),
-            TokenType::Colon => return String::from("Colon"),
-            TokenType::Period => return String::from("Period"),
-            TokenType::Comma => return String::from("Comma"),
-            TokenType::Or => return String::from("Pipe"),
-            TokenType::Underscore => return String::from("Underscore"),
-            TokenType::Caret => return String::from("Caret"),
-            TokenType::Hashtag => return String::from("Hashtag"),
-            TokenType::And => return String::from("Ampersand"),
-            TokenType::LeftCaret => return String::from("Left Caret/Less than"),
-            TokenType::RightCaret => return String::from("Right Caret/Greater than"),
-            TokenType::ClosedParen => return String::from("Closed Parenthesis"),
-            TokenType::OpenParen => return String::from("Open Parenthesis"),
-            TokenType::OpenBracket => return String::from("Open Bracket"),
-            TokenType::ClosedBracket => return String::from("Closed Bracket"),
-            TokenType::EOF => return String::from("EOF"),
-        }
+    // tokens that start a declaration, comments right above these are doc comments
+    pub fn starts_declaration(&self) -> bool {
+        matches!(
+            self,
+            TokenType::Package
+                | TokenType::Import
+                | TokenType::Func
+                | TokenType::Type
+                | TokenType::Var
+                | TokenType::Const
+        )
+    }
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            TokenType::Break
+                | TokenType::Case
+                | TokenType::Chan
+                | TokenType::Const
+                | TokenType::Continue
+                | TokenType::Default
+                | TokenType::Defer
+                | TokenType::Else
+                | TokenType::Fallthrough
+                | TokenType::For
+                | TokenType::Func
+                | TokenType::Go
+                | TokenType::Goto
+                | TokenType::If
+                | TokenType::Import
+                | TokenType::Interface
+                | TokenType::Map
+                | TokenType::Package
+                | TokenType::Range
+                | TokenType::Return
+                | TokenType::Select
+                | TokenType::Struct
+                | TokenType::Switch
+                | TokenType::Type
+                | TokenType::Var
+        )
+    }
+}
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            TokenType::Ident => "Identifier",
+            TokenType::Invalid => "Invalid",
+            TokenType::Minus => "Minus/Dash",
+            TokenType::Plus => "Plus",
+            TokenType::Assign => "Assign",
+            TokenType::Equals => "Equals",
+            TokenType::StringLiteral => "String Literal",
+            TokenType::IntLiteral => "Int Literal",
+            TokenType::FloatLiteral => "Float Literal",
+            TokenType::ImagLiteral => "Imaginary Literal",
+            TokenType::Operand => "Operand/Symbol",
+            TokenType::RuneLiteral => "Rune Literal",
+            TokenType::Star => "Star/Asterisk",
+            TokenType::Modulo => "Modulo",
+            TokenType::Bang => "Bang",
+            TokenType::FwdSlash => "Forwardslash",
+            TokenType::Semicolon => "Semi-Colon",
+            TokenType::Colon => "Colon",
+            TokenType::Period => "Period",
+            TokenType::Comma => "Comma",
+            TokenType::Or => "Pipe",
+            TokenType::Blank => "Blank Identifier",
+            TokenType::Caret => "Caret",
+            TokenType::And => "Ampersand",
+            TokenType::LeftCaret => "Left Caret/Less than",
+            TokenType::RightCaret => "Right Caret/Greater than",
+            TokenType::ClosedParen => "Closed Parenthesis",
+            TokenType::OpenParen => "Open Parenthesis",
+            TokenType::OpenBracket => "Open Bracket",
+            TokenType::ClosedBracket => "Closed Bracket",
+            TokenType::OpenSquare => "Open Square Bracket",
+            TokenType::ClosedSquare => "Closed Square Bracket",
+            TokenType::ColonAssign => "Colon Assign",
+            TokenType::NotEqual => "Not Equal",
+            TokenType::LessThanOrEqual => "Less than or Equal",
+            TokenType::GreaterThanOrEqual => "Greater than or Equal",
+            TokenType::Lshift => "Left Shift",
+            TokenType::Rshift => "Right Shift",
+            TokenType::BitClear => "Bit Clear",
+            TokenType::AndAnd => "Logical And",
+            TokenType::OrOr => "Logical Or",
+            TokenType::Arrow => "Arrow",
+            TokenType::Increment => "Increment",
+            TokenType::Decrement => "Decrement",
+            TokenType::Ellipsis => "Ellipsis",
+            TokenType::PlusAssign => "Plus Assign",
+            TokenType::MinusAssign => "Minus Assign",
+            TokenType::StarAssign => "Star Assign",
+            TokenType::SlashAssign => "Slash Assign",
+            TokenType::ModuloAssign => "Modulo Assign",
+            TokenType::AndAssign => "And Assign",
+            TokenType::OrAssign => "Or Assign",
+            TokenType::CaretAssign => "Caret Assign",
+            TokenType::BitClearAssign => "Bit Clear Assign",
+            TokenType::LshiftAssign => "Left Shift Assign",
+            TokenType::RshiftAssign => "Right Shift Assign",
+            TokenType::Break => "break",
+            TokenType::Case => "case",
+            TokenType::Chan => "chan",
+            TokenType::Const => "const",
+            TokenType::Continue => "continue",
+            TokenType::Default => "default",
+            TokenType::Defer => "defer",
+            TokenType::Else => "else",
+            TokenType::Fallthrough => "fallthrough",
+            TokenType::For => "for",
+            TokenType::Func => "func",
+            TokenType::Go => "go",
+            TokenType::Goto => "goto",
+            TokenType::If => "if",
+            TokenType::Import => "import",
+            TokenType::Interface => "interface",
+            TokenType::Map => "map",
+            TokenType::Package => "package",
+            TokenType::Range => "range",
+            TokenType::Return => "return",
+            TokenType::Select => "select",
+            TokenType::Struct => "struct",
+            TokenType::Switch => "switch",
+            TokenType::Type => "type",
+            TokenType::Var => "var",
+            TokenType::EOF => "EOF",
+        };
+        write!(f, "{}", s)
     }
 }
 // Spanner is a trait that returns a Span object,
@@ -395,7 +1282,12 @@ pub trait Spanner {
 }
 impl Spanner for Span {
     fn span(&self) -> Span {
-        return *self;
+        *self
+    }
+}
+impl Spanner for TS {
+    fn span(&self) -> Span {
+        self.span
     }
 }
 impl<T: Spanner> Spanner for Vec<T> {
@@ -403,14 +1295,14 @@ impl<T: Spanner> Spanner for Vec<T> {
         if self.is_empty() {
             return Span { beg: 0, end: 0 };
         }
-        return Span {
+        Span {
             beg: self.first().unwrap().span().beg,
             end: self.last().unwrap().span().end,
-        };
+        }
     }
 }
-// This will take in our input string and return a result type, meaning either Ok(Vec<Token>) or
-// Err(String) in this case..
+// Iterating the Lexer gives us one token plus its Span in the source at a time,
+// until it hands back EOF. If the source is malformed the errors are left in Lexer::errors.
 /*
     func main() {
         println("hello world")
@@ -421,37 +1313,486 @@ impl<T: Spanner> Spanner for Vec<T> {
 }
 This is our output of the above file:
 
-"StringLiteral: func"
-"StringLiteral: main"
+"Keyword: func"
+"Ident: main"
 "Open Parenthesis"
 "Closed Parenthesis"
 "Open Bracket"
-"StringLiteral: println"
+"Ident: println"
 "Open Parenthesis"
-"Quote"
-"StringLiteral: ello"
-"StringLiteral: world"
-"Quote"
+"StringLiteral: \"hello world\""
 "Closed Parenthesis"
-"StringLiteral: let"
-"StringLiteral: i"
-"Equals"
-"NumberLiteral: 0"
-"StringLiteral: for"
-"StringLiteral: i"
-"Left Carrot/Less than"
-"NumberLiteral: 100"
+"Semi-Colon"
+"Ident: let"
+"Ident: i"
+"Assign"
+"IntLiteral: 0"
+"Semi-Colon"
+"Keyword: for"
+"Ident: i"
+"Left Caret/Less than"
+"IntLiteral: 100"
 "Open Bracket"
-"StringLiteral: println"
+"Ident: println"
 "Open Parenthesis"
-"StringLiteral: i"
+"Ident: i"
 "Closed Parenthesis"
+"Semi-Colon"
 "Closed Bracket"
+"Semi-Colon"
 "Closed Bracket"
-success!
-"StringLiteral: main"
-"Period"
-"StringLiteral: p"
-Input file: main.p
-Output filename: filename
+"Semi-Colon"
     */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_map::SourceMap;
+    use TokenType::*;
+
+    // every token but the EOF, with its text, and the errors
+    fn lex(src: &str) -> (Vec<(TokenType, String)>, Vec<LexError>) {
+        let mut lexer = Lexer::new(src.to_string());
+        let tokens = lexer
+            .by_ref()
+            .filter(|ts| ts.token.kind != EOF)
+            .map(|ts| (ts.token.kind, ts.token.value.as_str().to_string()))
+            .collect();
+        (tokens, lexer.errors)
+    }
+    // the one token `src` lexes to, and any errors
+    fn single(src: &str) -> (TokenType, String, Vec<LexError>) {
+        let (mut tokens, errors) = lex(src);
+        // a literal at the end of the input is followed by an inserted semicolon
+        tokens.retain(|(kind, value)| !(*kind == Semicolon && value == "\n"));
+        assert_eq!(tokens.len(), 1, "{:?} lexed to {:?}", src, tokens);
+        let (kind, value) = tokens.pop().unwrap();
+        (kind, value, errors)
+    }
+
+    fn kinds(src: &str) -> Vec<TokenType> {
+        let (tokens, errors) = lex(src);
+        assert_eq!(errors, vec![], "{:?}", src);
+        tokens.into_iter().map(|(kind, _)| kind).collect()
+    }
+
+    #[test]
+    fn numbers() {
+        let table = [
+            ("0", IntLiteral),
+            ("42", IntLiteral),
+            ("1_000_000", IntLiteral),
+            ("0x1F", IntLiteral),
+            ("0X_1f", IntLiteral),
+            ("0x1e2", IntLiteral),
+            ("0o17", IntLiteral),
+            ("0O_7", IntLiteral),
+            ("0b1011", IntLiteral),
+            ("017", IntLiteral),
+            ("0_17", IntLiteral),
+            ("0.5", FloatLiteral),
+            (".5", FloatLiteral),
+            ("1.", FloatLiteral),
+            ("1e10", FloatLiteral),
+            ("1E-3", FloatLiteral),
+            ("09.5", FloatLiteral),
+            ("0x1p-2", FloatLiteral),
+            ("0x1.8p1", FloatLiteral),
+            ("0x.8p0", FloatLiteral),
+            ("1_0.2_5e+1_0", FloatLiteral),
+            ("1i", ImagLiteral),
+            ("09i", ImagLiteral),
+            ("0.5i", ImagLiteral),
+            ("1e3i", ImagLiteral),
+            ("0x1p2i", ImagLiteral),
+            ("0b11i", ImagLiteral),
+        ];
+        for (src, kind) in table {
+            let (got, value, errors) = single(src);
+            assert_eq!((got, errors), (kind, vec![]), "{}", src);
+            // the value is the literal as written
+            assert_eq!(value, src);
+        }
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        // the literal, where the error is, and what it says
+        let table = [
+            ("1__0", 2, "'_' must separate successive digits"),
+            ("1_", 1, "'_' must separate successive digits"),
+            ("0x", 0, "hexadecimal literal has no digits"),
+            ("0b102", 4, "invalid digit '2' in binary literal"),
+            ("08", 1, "invalid digit '8' in octal literal"),
+            ("0o19", 3, "invalid digit '9' in octal literal"),
+            ("0x1.8", 0, "hexadecimal mantissa requires a 'p' exponent"),
+            ("0o1e2", 3, "'e' exponent requires decimal mantissa"),
+            ("1p2", 1, "'p' exponent requires hexadecimal mantissa"),
+            ("1e", 1, "exponent has no digits"),
+            ("1e+", 1, "exponent has no digits"),
+            ("0o1.2", 0, "invalid radix point in octal literal"),
+            ("0b1.0", 0, "invalid radix point in binary literal"),
+        ];
+        for (src, pos, msg) in table {
+            let (_, value, errors) = single(src);
+            assert_eq!(value, src);
+            assert_eq!(errors.len(), 1, "{}: {:?}", src, errors);
+            assert_eq!(errors[0].span.beg, pos, "{}", src);
+            assert_eq!(errors[0].kind.to_string(), msg, "{}", src);
+        }
+    }
+
+    #[test]
+    fn keywords() {
+        let words = "break case chan const continue default defer else fallthrough for func go \
+                     goto if import interface map package range return select struct switch \
+                     type var";
+        let (tokens, errors) = lex(words);
+        assert_eq!(errors, vec![]);
+        let got: Vec<TokenType> = tokens.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(
+            got,
+            vec![
+                Break,
+                Case,
+                Chan,
+                Const,
+                Continue,
+                Default,
+                Defer,
+                Else,
+                Fallthrough,
+                For,
+                Func,
+                Go,
+                Goto,
+                If,
+                Import,
+                Interface,
+                Map,
+                Package,
+                Range,
+                Return,
+                Select,
+                Struct,
+                Switch,
+                Type,
+                Var,
+            ]
+        );
+        // keywords keep their text as the value
+        let values: Vec<&str> = tokens.iter().map(|(_, value)| value.as_str()).collect();
+        assert_eq!(values, words.split_whitespace().collect::<Vec<_>>());
+        // only the exact words are reserved
+        assert_eq!(
+            kinds("Func funcs _func iff"),
+            vec![Ident, Ident, Ident, Ident, Semicolon]
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let table = [
+            (r#""""#, ""),
+            (r#""hello""#, "hello"),
+            (r#""a\tb\n""#, "a\tb\n"),
+            (r#""\"\\""#, "\"\\"),
+            (r#""\x41\101""#, "AA"),
+            (r#""é\U0001F600""#, "é😀"),
+            (r#""日本""#, "日本"),
+            ("`a\\n\r\nb`", "a\\n\nb"),
+            ("`\"`", "\""),
+        ];
+        for (src, value) in table {
+            let (kind, got, errors) = single(src);
+            assert_eq!((kind, errors), (StringLiteral, vec![]), "{}", src);
+            assert_eq!(got, value, "{}", src);
+        }
+    }
+    #[test]
+    fn runes() {
+        let table = [
+            ("'a'", "a"),
+            ("'\\n'", "\n"),
+            ("'\\''", "'"),
+            ("'\\x41'", "A"),
+            ("'\\101'", "A"),
+            ("'\\u00e9'", "é"),
+            ("'本'", "本"),
+        ];
+        for (src, value) in table {
+            let (kind, got, errors) = single(src);
+            assert_eq!((kind, errors), (RuneLiteral, vec![]), "{}", src);
+            assert_eq!(got, value, "{}", src);
+        }
+    }
+    #[test]
+    fn byte_escapes_keep_their_bytes() {
+        let mut lexer = Lexer::new(r#""\xff" "\xfe" "\377" "\xe6\x97\xa5""#.to_string());
+        let values: Vec<Symbol> = lexer
+            .by_ref()
+            .filter(|ts| ts.token.kind == StringLiteral)
+            .map(|ts| ts.token.value)
+            .collect();
+        assert_eq!(lexer.errors, vec![]);
+        assert_eq!(values[0].as_bytes(), b"\xff");
+        assert_eq!(values[1].as_bytes(), b"\xfe");
+        assert_ne!(values[0], values[1]);
+        // the same byte written as octal
+        assert_eq!(values[0], values[2]);
+        // bytes that happen to be UTF-8 are the same string as writing it out
+        assert_eq!(values[3], Symbol::intern("日"));
+    }
+
+    #[test]
+    fn operators() {
+        let table: &[(&str, TokenType)] = &[
+            ("+", Plus),
+            ("-", Minus),
+            ("*", Star),
+            ("/", FwdSlash),
+            ("%", Modulo),
+            ("&", And),
+            ("|", Or),
+            ("^", Caret),
+            ("<", LeftCaret),
+            (">", RightCaret),
+            ("=", Assign),
+            ("!", Bang),
+            ("~", Operand),
+            ("<<", Lshift),
+            (">>", Rshift),
+            ("&^", BitClear),
+            ("&&", AndAnd),
+            ("||", OrOr),
+            ("<-", Arrow),
+            ("==", Equals),
+            ("!=", NotEqual),
+            ("<=", LessThanOrEqual),
+            (">=", GreaterThanOrEqual),
+            (":=", ColonAssign),
+            ("...", Ellipsis),
+            ("+=", PlusAssign),
+            ("-=", MinusAssign),
+            ("*=", StarAssign),
+            ("/=", SlashAssign),
+            ("%=", ModuloAssign),
+            ("&=", AndAssign),
+            ("|=", OrAssign),
+            ("^=", CaretAssign),
+            ("<<=", LshiftAssign),
+            (">>=", RshiftAssign),
+            ("&^=", BitClearAssign),
+        ];
+        for (src, kind) in table {
+            assert_eq!(kinds(src), vec![*kind], "{}", src);
+        }
+        // the longest operator wins
+        assert_eq!(kinds("a<<=b"), vec![Ident, LshiftAssign, Ident, Semicolon]);
+        assert_eq!(kinds("a&^b"), vec![Ident, BitClear, Ident, Semicolon]);
+        assert_eq!(kinds("a<-b"), vec![Ident, Arrow, Ident, Semicolon]);
+        assert_eq!(
+            kinds("a<=-b"),
+            vec![Ident, LessThanOrEqual, Minus, Ident, Semicolon]
+        );
+        assert_eq!(kinds("a.b"), vec![Ident, Period, Ident, Semicolon]);
+        assert_eq!(kinds("x..."), vec![Ident, Ellipsis]);
+        assert_eq!(kinds("x.."), vec![Ident, Period, Period]);
+    }
+
+    #[test]
+    fn semicolon_insertion() {
+        let table: &[(&str, &[TokenType])] = &[
+            ("x\n", &[Ident, Semicolon]),
+            ("x // c\n", &[Ident, Semicolon]),
+            ("x /* c */\n", &[Ident, Semicolon]),
+            ("x /* a\nb */ y", &[Ident, Semicolon, Ident, Semicolon]),
+            ("return\n", &[Return, Semicolon]),
+            (
+                "break\ncontinue\nfallthrough\n",
+                &[
+                    Break,
+                    Semicolon,
+                    Continue,
+                    Semicolon,
+                    Fallthrough,
+                    Semicolon,
+                ],
+            ),
+            (
+                "x++\ny--\n",
+                &[Ident, Increment, Semicolon, Ident, Decrement, Semicolon],
+            ),
+            ("f()\n", &[Ident, OpenParen, ClosedParen, Semicolon]),
+            (
+                "a[0]\n",
+                &[Ident, OpenSquare, IntLiteral, ClosedSquare, Semicolon],
+            ),
+            ("}\n", &[ClosedBracket, Semicolon]),
+            (
+                "1.5\n'a'\n\"s\"\n",
+                &[
+                    FloatLiteral,
+                    Semicolon,
+                    RuneLiteral,
+                    Semicolon,
+                    StringLiteral,
+                    Semicolon,
+                ],
+            ),
+            // no semicolon after an operator, a comma or an opening bracket
+            ("a +\nb", &[Ident, Plus, Ident, Semicolon]),
+            (
+                "f(a,\nb)",
+                &[
+                    Ident,
+                    OpenParen,
+                    Ident,
+                    Comma,
+                    Ident,
+                    ClosedParen,
+                    Semicolon,
+                ],
+            ),
+            ("{\n}", &[OpenBracket, ClosedBracket, Semicolon]),
+            ("func\n", &[Func]),
+            // at the end of the input just like at the end of a line
+            ("x", &[Ident, Semicolon]),
+            ("\n\n", &[]),
+        ];
+        for (src, want) in table {
+            assert_eq!(kinds(src), *want, "{:?}", src);
+        }
+    }
+    #[test]
+    fn inserted_semicolon_sits_at_the_newline() {
+        let tokens: Vec<TS> = Lexer::new("x // c\ny".to_string()).collect();
+        let semi = &tokens[1];
+        assert_eq!(semi.token.kind, Semicolon);
+        assert!(semi.token.synthetic);
+        assert_eq!(semi.span, Span { beg: 6, end: 6 });
+        assert_eq!(tokens[2].span, Span { beg: 7, end: 8 });
+    }
+
+    #[test]
+    fn bad_literals() {
+        let table = [
+            ("\"abc", LexErrorKind::UnterminatedString),
+            ("`abc", LexErrorKind::UnterminatedRawString),
+            ("'a", LexErrorKind::UnterminatedRune),
+            ("/* abc", LexErrorKind::UnterminatedComment),
+            ("a § b", LexErrorKind::UnknownChar('§')),
+            ("a # b", LexErrorKind::UnknownChar('#')),
+            ("a \\ b", LexErrorKind::UnknownChar('\\')),
+            ("a ? b", LexErrorKind::UnknownChar('?')),
+            ("a @ b", LexErrorKind::UnknownChar('@')),
+            ("$a", LexErrorKind::UnknownChar('$')),
+        ];
+        for (src, kind) in table {
+            let (_, errors) = lex(src);
+            assert_eq!(errors.len(), 1, "{}: {:?}", src, errors);
+            assert_eq!(errors[0].kind, kind, "{}", src);
+        }
+        let table = [
+            (r#""\q""#, 1),
+            (r#""\x4""#, 1),
+            (r#""\400""#, 1),
+            (r#""\uD800""#, 1),
+            (r#""\U00110000""#, 1),
+            ("'ab'", 0),
+            ("''", 0),
+            ("'\\\"'", 1),
+        ];
+        for (src, pos) in table {
+            let (_, errors) = lex(src);
+            assert_eq!(errors.len(), 1, "{}: {:?}", src, errors);
+            assert!(
+                matches!(
+                    errors[0].kind,
+                    LexErrorKind::InvalidEscape(_) | LexErrorKind::InvalidRune(_)
+                ),
+                "{}: {:?}",
+                src,
+                errors
+            );
+            assert_eq!(errors[0].span.beg, pos, "{}", src);
+        }
+    }
+    #[test]
+    fn invalid_utf8() {
+        let mut map = SourceMap::new();
+        let id = map.add_bytes("bad.go".to_string(), b"x := \"a\xffb\"\n");
+        let mut lexer = Lexer::for_file(map.file(id));
+        lexer.by_ref().for_each(drop);
+        assert_eq!(lexer.errors.len(), 1);
+        assert_eq!(lexer.errors[0].kind, LexErrorKind::InvalidUtf8);
+        assert_eq!(lexer.errors[0].span.beg, 7);
+    }
+
+    #[test]
+    fn unknown_chars_are_invalid_tokens() {
+        let (tokens, errors) = lex("var x = a # b");
+        let got: Vec<TokenType> = tokens.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(
+            got,
+            vec![Var, Ident, Assign, Ident, Invalid, Ident, Semicolon]
+        );
+        assert_eq!(tokens[4].1, "#");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span, Span { beg: 10, end: 11 });
+        assert_eq!(errors[0].kind.to_string(), "invalid character '#'");
+    }
+
+    #[test]
+    fn identifiers() {
+        for src in ["x", "_x9", "café", "π", "日本語", "x١"] {
+            assert_eq!(kinds(src), vec![Ident, Semicolon], "{}", src);
+        }
+        assert_eq!(kinds("_"), vec![Blank, Semicolon]);
+        // letter numbers, other numbers and combining marks aren't letters in Go
+        for src in ["x²", "Ⅻ", "e\u{301}"] {
+            let (_, errors) = lex(src);
+            assert_eq!(errors.len(), 1, "{}", src);
+            assert!(matches!(errors[0].kind, LexErrorKind::UnknownChar(_)));
+        }
+    }
+    #[test]
+    fn spans() {
+        let tokens: Vec<Span> = Lexer::new("ab := \"é\"".to_string())
+            .map(|ts| ts.span)
+            .collect();
+        let want = [(0, 2), (3, 5), (6, 10), (10, 10), (10, 10)];
+        let want: Vec<Span> = want.iter().map(|&(beg, end)| Span { beg, end }).collect();
+        assert_eq!(tokens, want);
+    }
+
+    #[test]
+    fn lossless_round_trip() {
+        let src = "// Package p\npackage p\n\n/* doc */\n// more doc\nfunc f() { // body\n\tx := 1 /* one */ + 2\n}\n";
+        let mut map = SourceMap::new();
+        let id = map.add_file("p.go".to_string(), src.to_string());
+        let file = map.file(id);
+        let mut out = String::new();
+        let mut func_leading = Vec::new();
+        for ts in Lexer::lossless(file) {
+            for trivia in &ts.leading {
+                out.push_str(file.text(trivia.span));
+            }
+            out.push_str(file.text(ts.span));
+            for trivia in &ts.trailing {
+                out.push_str(file.text(trivia.span));
+            }
+            if ts.token.kind == Func {
+                func_leading = ts.leading.clone();
+            }
+        }
+        assert_eq!(out, src);
+        // both comments right above func are its doc comment
+        let docs: Vec<&str> = func_leading
+            .iter()
+            .filter(|t| t.kind == TriviaKind::DocComment)
+            .map(|t| file.text(t.span))
+            .collect();
+        assert_eq!(docs, vec!["/* doc */", "// more doc"]);
+    }
+}
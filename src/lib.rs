@@ -0,0 +1,5 @@
+pub mod ast;
+pub mod intern;
+pub mod lexer;
+pub mod parser;
+pub mod source_map;
@@ -1,69 +1,178 @@
-mod lexer;
-mod parser;
-use crate::lexer::tokenizer;
+use compiler::lexer::{Lexer, Span, TS};
+use compiler::parser::Parser;
+use compiler::source_map::SourceMap;
 use std::env;
-use std::fs::read_to_string;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum Input {
-    File(String),
-    Option(Flag),
+// What the compiler should write out, instead of running the whole pipeline.
+// This is mostly for debugging the compiler itself.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Emit {
+    Nothing,
+    Tokens,
 }
 
-impl Input {
-    pub fn to_string(&self) -> String {
-        match self {
-            Input::File(s) => s.clone(),
-            Input::Option(_) => String::from("Option variant"),
-        }
-    }
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Format {
+    // one token per line, lined up in columns
+    Table,
+    // one JSON object per line, easy to diff or feed to other tools
+    Json,
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum Flag {
-    Output,
-    Input,
-    Help,
-}
+const USAGE: &str =
+    "Usage: compiler -i input_file [-o output_filename] [--emit=tokens] [--format=table|json]";
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let mut input_file: Option<String> = None;
     let mut output_filename: Option<String> = None;
+    let mut emit = Emit::Nothing;
+    let mut format = Format::Table;
 
-    for i in 1..args.len() {
+    let mut i = 1;
+    while i < args.len() {
         match args[i].as_str() {
             "-i" if i < args.len() - 1 => {
                 input_file = Some(args[i + 1].clone());
+                i += 1;
             }
             "-o" if i < args.len() - 1 => {
                 output_filename = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--emit=tokens" => emit = Emit::Tokens,
+            "--format=table" => format = Format::Table,
+            "--format=json" => format = Format::Json,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            arg => {
+                eprintln!("Invalid argument: {}\n{}", arg, USAGE);
+                process::exit(2);
             }
-            _ => (),
         }
+        i += 1;
     }
 
-    match (input_file, output_filename) {
-        (Some(file), Some(output)) => {
-            match read_to_string(file.clone()) {
-                Ok(lines) => match tokenizer(lines) {
-                    Ok(_tokens) => (),
-                    Err(err) => println!("{}", err),
-                },
-                Err(err) => println!("{}", err),
-            }
-            match tokenizer(file.clone()) {
-                Ok(_tokens) => {
-                    for item in _tokens.iter() {
-                        std::fs::write("./output.txt", item.to_string()).unwrap();
-                    }
+    let file = match input_file {
+        Some(file) => file,
+        None => {
+            eprintln!("Invalid input. {}", USAGE);
+            process::exit(2);
+        }
+    };
+    let mut source_map = SourceMap::new();
+    let id = match source_map.load_file(&file) {
+        Ok(id) => id,
+        Err(err) => {
+            eprintln!("{}: {}", file, err);
+            process::exit(1);
+        }
+    };
+
+    let mut lexer = Lexer::for_file(source_map.file(id));
+    let mut parse_errors = Vec::new();
+    if emit == Emit::Tokens {
+        // no output file (or "-") means stdout
+        let result = match output_filename.as_deref() {
+            Some(path) if path != "-" => File::create(path).and_then(|f| {
+                let mut out = BufWriter::new(f);
+                write_tokens(&mut out, lexer.by_ref(), &source_map, format)?;
+                out.flush()
+            }),
+            _ => write_tokens(
+                &mut io::stdout().lock(),
+                lexer.by_ref(),
+                &source_map,
+                format,
+            ),
+        };
+        if let Err(err) = result {
+            eprintln!("failed to write tokens: {}", err);
+            process::exit(1);
+        }
+    } else {
+        // nothing after the parser yet, all we can do is report what it found
+        let mut parser = Parser::new(lexer.by_ref());
+        parser.parse();
+        parse_errors = parser.errors;
+    }
+
+    // lexer and parser errors interleaved, in the order they appear in the file
+    let mut errors: Vec<(Span, String)> = Vec::new();
+    for err in &lexer.errors {
+        errors.push((err.span, err.kind.to_string()));
+    }
+    for err in &parse_errors {
+        errors.push((err.span, err.kind.to_string()));
+    }
+    errors.sort_by_key(|(span, _)| span.beg);
+    for (span, msg) in &errors {
+        match source_map.location(*span) {
+            Some(loc) => eprintln!("{}: {}", loc, msg),
+            None => eprintln!("{} at {}..{}", msg, span.beg, span.end),
+        }
+    }
+    if !errors.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn write_tokens(
+    out: &mut impl Write,
+    tokens: impl Iterator<Item = TS>,
+    source_map: &SourceMap,
+    format: Format,
+) -> io::Result<()> {
+    for ts in tokens {
+        let (line, col) = match source_map.location(ts.span) {
+            Some(loc) => (loc.beg.line, loc.beg.col),
+            None => (0, 0),
+        };
+        let value = ts.token.value.as_str();
+        match format {
+            Format::Table => {
+                let pos = format!("{}:{}", line, col);
+                let kind = format!("{:?}", ts.token.kind);
+                write!(out, "{:<10} {:<20} {}", pos, kind, value.escape_debug())?;
+                if ts.token.synthetic {
+                    write!(out, " (inserted)")?;
                 }
-                Err(err) => println!("{}", err),
+                writeln!(out)?;
             }
-            println!("Input file: {}", file);
-            println!("Output filename: {}", output);
+            Format::Json => writeln!(
+                out,
+                "{{\"kind\":\"{:?}\",\"value\":{},\"line\":{},\"col\":{},\"beg\":{},\"end\":{},\"synthetic\":{}}}",
+                ts.token.kind,
+                json_string(value),
+                line,
+                col,
+                ts.span.beg,
+                ts.span.end,
+                ts.token.synthetic
+            )?,
+        }
+    }
+    Ok(())
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
         }
-        _ => println!("Invalid input. Usage: compiler -i input_file -o output_filename"),
     }
-    println!("success?");
+    out.push('"');
+    out
 }
@@ -1,17 +1,98 @@
 /* VERY beginning stages, very little work done yet as lexer is reworked */
+use crate::ast::{ImportName, ImportSpec, SourceFile};
+use crate::intern::Symbol;
 use crate::lexer::Span;
+use crate::lexer::Spanner;
 use crate::lexer::Token;
 use crate::lexer::TokenType;
 use crate::lexer::TS;
+use std::fmt;
 use std::iter::Peekable;
 
 pub struct Parser<R: Iterator<Item = TS>> {
-    reader: Peekable<R>, // Our source of tokens
-    token: Token,        // the current token being parsed
-    span: Span,          // span represents the relative location in the source
-                         // code that our current token resides. This is for error
-                         // messages, warnings, diagnostics
+    // Our source of tokens
+    reader: Peekable<R>,
+    // the current token being parsed
+    token: Token,
+    // span represents the relative location in the source code that our current
+    // token resides. This is for error messages, warnings, diagnostics
+    span: Span,
+    // where the previous token ended, so a node's span can run up to the last
+    // token that belongs to it
+    prev_end: u32,
+    // Go's exprLev: < 0 in the header of an if, for or switch, where a `{` starts
+    // the body rather than a composite literal, and > 0 inside parens or brackets
+    // where it can't be the body.
+    expr_lev: i32,
+    // Set while parsing the tag of a switch, the only place `x.(type)` may appear.
+    // An operator or a nested block means we are no longer at the guard itself.
+    type_switch_tag: bool,
+    // Everything that went wrong so far. The parser reports an error, skips ahead
+    // to the next statement or declaration and keeps going, so a file can have many.
+    pub errors: Vec<ParseError>,
 }
+
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub enum ParseErrorKind {
+    // the token wasn't any of the ones that could come next
+    UnexpectedToken {
+        expected: Vec<TokenType>,
+        found: TokenType,
+    },
+    // the token can't start what we were looking for, like an expression or a type
+    Expected {
+        what: &'static str,
+        found: TokenType,
+    },
+    // it parses, but the language doesn't allow it there
+    Invalid(&'static str),
+}
+
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+}
+
+impl ParseError {
+    fn invalid(msg: &'static str, span: Span) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::Invalid(msg),
+            span,
+        }
+    }
+}
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::UnexpectedToken { expected, found } => {
+                write!(f, "expected ")?;
+                for (i, kind) in expected.iter().enumerate() {
+                    if i > 0 {
+                        let sep = if i == expected.len() - 1 {
+                            " or "
+                        } else {
+                            ", "
+                        };
+                        write!(f, "{}", sep)?;
+                    }
+                    write!(f, "{}", kind)?;
+                }
+                write!(f, ", found {}", found)
+            }
+            ParseErrorKind::Expected { what, found } => {
+                write!(f, "expected {}, found {}", what, found)
+            }
+            ParseErrorKind::Invalid(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}..{}", self.kind, self.span.beg, self.span.end)
+    }
+}
+
 macro_rules! enum_from_impl {
     ($enum_type:ident, $(($enum_variant:ident, $inner_type:ty)),*) => {
         $(
@@ -24,21 +105,26 @@ macro_rules! enum_from_impl {
     }
 }
 
-enum_from_impl!(Statement,
-                (Decl, DeclStmt),
-                (Labeled, LabeledStmt),
-                (Simple, SimpleStmt),
-                (Return, ReturnStmt),
-                (Break, BreakStmt),
-                (Continue, ContinueStmt),
-                (Fallthrough, FallthroughStmt),
-                (Block, Block),
-                (If, IfStmt),
-                (Select, SelectStmt),
-                (For, ForStmt),
-                (Defer, DeferStmt),
-                (Empty, EmptyStmt));
-
+enum_from_impl!(
+    Statement,
+    (Decl, DeclStmt),
+    (Labeled, LabeledStmt),
+    (Simple, SimpleStmt),
+    (Return, ReturnStmt),
+    (Break, BreakStmt),
+    (Continue, ContinueStmt),
+    (Goto, GotoStmt),
+    (Fallthrough, FallthroughStmt),
+    (Block, Block),
+    (If, IfStmt),
+    (Switch, SwitchStmt),
+    (TypeSwitch, TypeSwitchStmt),
+    (Select, SelectStmt),
+    (For, ForStmt),
+    (Go, GoStmt),
+    (Defer, DeferStmt),
+    (Empty, EmptyStmt)
+);
 
 /// A simple statement.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -57,25 +143,32 @@ pub struct ForStmt {
     pub body: Block,
 }
 
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ForHeader {
-    Condition(Expr),
+    Condition(Spanned<Expr>),
     ForClause(ForClause),
+    Range(RangeClause),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ForClause {
     pub init: Option<SimpleStmt>,
-    pub condition: Option<Expr>,
+    pub condition: Option<Spanned<Expr>>,
     pub post: Option<SimpleStmt>,
 }
 
+/// `for k, v := range x`, `for k = range x` or just `for range x`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeClause {
+    pub vars: Option<IterVars>,
+    pub expr: Spanned<Expr>,
+}
 
+/// Idents when the range clause declares them with :=, Exprs when it assigns with =.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum IterVars {
     Exprs(Vec<Spanned<Expr>>),
-    Idents(Vec<Spanned<String>>),
+    Idents(Vec<Ident>),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -95,6 +188,69 @@ pub enum DeclStmt {
     VarDecl(VarDecl),
 }
 
+/// A var, const or type declaration, with one spec or a parenthesized group of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarDecl {
+    pub specs: Vec<VarSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarSpec {
+    pub names: Vec<Ident>,
+    pub typ: Option<Type>,
+    pub values: Vec<Spanned<Expr>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstDecl {
+    pub specs: Vec<ConstSpec>,
+}
+
+/// A spec in a const group that leaves out its expressions repeats the type and
+/// expressions of the spec before it, the parser fills those in. `iota` is the
+/// index of the spec in its group, which is the value iota has in its expressions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstSpec {
+    pub names: Vec<Ident>,
+    pub typ: Option<Type>,
+    pub values: Vec<Spanned<Expr>>,
+    pub iota: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeDecl {
+    pub specs: Vec<TypeSpec>,
+}
+
+/// `type A = B` is an alias, another name for B. `type A B` defines a new type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeSpec {
+    pub name: Ident,
+    pub type_params: Vec<TypeParamDecl>,
+    pub alias: bool,
+    pub typ: Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendStmt {
+    pub channel: Spanned<Expr>,
+    pub value: Spanned<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncDecStmt {
+    pub expr: Spanned<Expr>,
+    /// true for ++, false for --
+    pub inc: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShortVarDecl {
+    pub names: Vec<Ident>,
+    pub values: Vec<Spanned<Expr>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Statement {
     Decl(DeclStmt),
     Labeled(LabeledStmt),
@@ -102,40 +258,2095 @@ pub enum Statement {
     Return(ReturnStmt),
     Break(BreakStmt),
     Continue(ContinueStmt),
+    Goto(GotoStmt),
+    Fallthrough(FallthroughStmt),
     Block(Block),
     If(IfStmt),
+    Switch(SwitchStmt),
+    TypeSwitch(TypeSwitchStmt),
     Select(SelectStmt),
     For(ForStmt),
+    Go(GoStmt),
     Defer(DeferStmt),
     Empty(EmptyStmt),
+    /// A statement that didn't parse, the error is in Parser::errors.
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabeledStmt {
+    pub label: Ident,
+    pub stmt: Box<Spanned<Statement>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReturnStmt {
+    pub results: Vec<Spanned<Expr>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakStmt {
+    pub label: Option<Ident>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContinueStmt {
+    pub label: Option<Ident>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GotoStmt {
+    pub label: Ident,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FallthroughStmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub stmts: Vec<Spanned<Statement>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IfStmt {
+    pub init: Option<SimpleStmt>,
+    pub cond: Spanned<Expr>,
+    pub then: Block,
+    pub els: Option<Box<Else>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Else {
+    If(IfStmt),
+    Block(Block),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwitchStmt {
+    pub init: Option<SimpleStmt>,
+    pub tag: Option<Spanned<Expr>>,
+    pub clauses: Vec<CaseClause<Spanned<Expr>>>,
+}
+
+/// switch v := x.(type) { ... }, `binding` is the v.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeSwitchStmt {
+    pub init: Option<SimpleStmt>,
+    pub binding: Option<Ident>,
+    pub expr: Box<Spanned<PrimaryExpr>>,
+    pub clauses: Vec<CaseClause<Type>>,
+}
+
+/// A case of an expression switch (T is an expression) or a type switch (T is a
+/// type). `case` is None for the default clause.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseClause<T> {
+    pub case: Option<Vec<T>>,
+    pub body: Vec<Spanned<Statement>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectStmt {
+    pub clauses: Vec<CommClause>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommClause {
+    pub case: CommCase,
+    pub body: Vec<Spanned<Statement>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommCase {
+    Send(SendStmt),
+    Recv(RecvStmt),
+    Default,
+    /// A case that is neither a send nor a receive, the error is in Parser::errors.
+    Error,
+}
+
+/// `case v, ok := <-ch`, `case v = <-ch` or just `case <-ch`. The variables
+/// are the same as in a range clause, Idents with := and Exprs with =.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecvStmt {
+    pub vars: Option<IterVars>,
+    pub recv: Spanned<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoStmt {
+    pub call: Spanned<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeferStmt {
+    pub call: Spanned<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TopLevelDecl {
+    Decl(DeclStmt),
+    Func(FuncDecl),
+    Method(MethodDecl),
+    /// A declaration that didn't parse, the error is in Parser::errors.
+    Error,
 }
+
+/// A function without a body is implemented outside of Go, like in assembly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuncDecl {
+    pub name: Ident,
+    pub type_params: Vec<TypeParamDecl>,
+    pub signature: Signature,
+    pub body: Option<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodDecl {
+    pub receiver: ParamDecl,
+    pub name: Ident,
+    pub signature: Signature,
+    pub body: Option<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub params: Vec<ParamDecl>,
+    pub results: Vec<ParamDecl>,
+}
+
+/// One group of parameters sharing a type, `a, b int` has two names and `int`
+/// on its own has none. Only the last parameter can be variadic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamDecl {
+    pub names: Vec<Ident>,
+    pub typ: Type,
+    pub variadic: bool,
+}
+
+/// One group of type parameters sharing a constraint, `K, V any` in `[K, V any]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeParamDecl {
+    pub names: Vec<Ident>,
+    pub constraint: Vec<TypeTerm>,
+}
+
+/// A node together with the Span of source it was parsed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spanned<T> {
+    pub span: Span,
+    pub item: T,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(span: Span, item: T) -> Spanned<T> {
+        Spanned { span, item }
+    }
+}
+
+impl<T> Spanner for Spanned<T> {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// Names are interned, comparing two identifiers is comparing two Symbols.
+pub type Ident = Spanned<Symbol>;
+
 // We will implement the following funcitons on our Parser object:
 // new: to create a new Parser object, and parse: to output the result
 // of parsing each following token
 impl<R: Iterator<Item = TS>> Parser<R> {
     pub fn new(mut it: R) -> Parser<R> {
         let first = it.next().unwrap();
-        return Parser {
+        Parser {
             token: first.token,
             span: first.span,
+            prev_end: first.span.beg,
+            expr_lev: 0,
+            type_switch_tag: false,
+            errors: Vec::new(),
             reader: it.peekable(),
+        }
+    }
+    // SourceFile = PackageClause ";" { ImportDecl ";" } { TopLevelDecl ";" } .
+    // Always hands back a SourceFile, whatever didn't parse is an Error node in
+    // it and the reasons are in self.errors.
+    pub fn parse(&mut self) -> SourceFile {
+        let beg = self.span.beg;
+        let package = match self.parse_package_clause() {
+            Ok(package) => package,
+            Err(err) => {
+                self.errors.push(err);
+                self.sync_decl();
+                // an empty name where the package name should have been
+                Spanned::new(Span { beg, end: beg }, Symbol::EMPTY)
+            }
         };
+        let mut imports = Vec::new();
+        let mut decls = Vec::new();
+        while self.token.kind != TokenType::EOF {
+            let beg = self.span.beg;
+            let import = self.token.kind == TokenType::Import;
+            let decl = if import {
+                if !decls.is_empty() {
+                    self.error("imports must appear before other declarations", self.span);
+                }
+                self.parse_import_decl(&mut imports).map(|_| None)
+            } else {
+                self.parse_top_level_decl().map(Some)
+            };
+            match decl {
+                Ok(decl) => {
+                    decls.extend(decl);
+                    if let Err(err) = self.expect_semi() {
+                        self.errors.push(err);
+                        self.sync_decl();
+                    }
+                }
+                Err(err) => {
+                    self.errors.push(err);
+                    self.sync_decl();
+                    // a broken import is still an import, it mustn't make the
+                    // imports after it look out of place
+                    if !import {
+                        decls.push(Spanned::new(self.span_from(beg), TopLevelDecl::Error));
+                    }
+                }
+            }
+        }
+        SourceFile {
+            package,
+            imports,
+            decls,
+        }
+    }
+    // PackageClause = "package" PackageName .
+    fn parse_package_clause(&mut self) -> Result<Ident, ParseError> {
+        self.expect(TokenType::Package)?;
+        let package = self.parse_ident()?;
+        if package.item.as_str() == "_" {
+            self.error("invalid package name _", package.span);
+        }
+        self.expect_semi()?;
+        Ok(package)
+    }
+    // ImportDecl = "import" ( ImportSpec | "(" { ImportSpec ";" } ")" ) .
+    fn parse_import_decl(&mut self, imports: &mut Vec<ImportSpec>) -> Result<(), ParseError> {
+        self.expect(TokenType::Import)?;
+        self.parse_group(|p| {
+            imports.push(p.parse_import_spec()?);
+            Ok(())
+        })
+    }
+    // ImportSpec = [ "." | PackageName ] ImportPath .
+    fn parse_import_spec(&mut self) -> Result<ImportSpec, ParseError> {
+        let name = match self.token.kind {
+            TokenType::Period => {
+                self.advance();
+                ImportName::Dot
+            }
+            TokenType::Blank => {
+                self.advance();
+                ImportName::Blank
+            }
+            TokenType::Ident => ImportName::Alias(self.parse_ident()?),
+            _ => ImportName::Default,
+        };
+        if self.token.kind != TokenType::StringLiteral {
+            return Err(self.unexpected("import path"));
+        }
+        let span = self.span;
+        let path = self.advance().value;
+        if path.is_empty() {
+            self.error("import path is empty", span);
+        }
+        Ok(ImportSpec {
+            name,
+            path: Spanned::new(span, path),
+        })
     }
-    pub fn parse(mut self) -> Result<AST, String> {
-        fn advance(&mut self) -> Token {
-            let next = self.reader.next();
 
-            if let Some(Token { span, token }) = next {
+    // Moves on to the next token and hands back the one we were on
+    fn advance(&mut self) -> Token {
+        let prev = self.token;
+        self.prev_end = self.span.end;
+        match self.reader.next() {
+            Some(TS { token, span, .. }) => {
                 self.token = token;
                 self.span = span;
+            }
+            // the lexer hands out a single EOF, keep returning it after that
+            None => {
+                self.token = Token::from(TokenType::EOF);
+                self.span = Span {
+                    beg: self.prev_end,
+                    end: self.prev_end,
+                };
+            }
+        }
+        prev
+    }
+    fn expect(&mut self, kind: TokenType) -> Result<Span, ParseError> {
+        if self.token.kind != kind {
+            return Err(self.unexpected_token(&[kind]));
+        }
+        let span = self.span;
+        self.advance();
+        Ok(span)
+    }
+    // The semicolon at the end of a top level declaration, which the last one
+    // in the file doesn't need
+    fn expect_semi(&mut self) -> Result<(), ParseError> {
+        if self.token.kind != TokenType::EOF {
+            self.expect(TokenType::Semicolon)?;
+        }
+        Ok(())
+    }
+    fn eat(&mut self, kind: TokenType) -> bool {
+        if self.token.kind == kind {
+            self.advance();
+            return true;
+        }
+        false
+    }
+    // The current token isn't what `what` has to start with
+    fn unexpected(&self, what: &'static str) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::Expected {
+                what,
+                found: self.token.kind,
+            },
+            span: self.span,
+        }
+    }
+    // The current token isn't any of `expected`
+    fn unexpected_token(&self, expected: &[TokenType]) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::UnexpectedToken {
+                expected: expected.to_vec(),
+                found: self.token.kind,
+            },
+            span: self.span,
+        }
+    }
+    // For mistakes that don't stop us from building the node, we note them and carry on
+    fn error(&mut self, msg: &'static str, span: Span) {
+        self.errors.push(ParseError::invalid(msg, span));
+    }
+    // Skip to the start of the next statement after an error. A `;` at the
+    // current nesting level ends the broken statement and is consumed, the
+    // end of the enclosing block or clause is left for the caller.
+    fn sync_stmt(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.token.kind {
+                TokenType::EOF => return,
+                TokenType::OpenBracket => depth += 1,
+                TokenType::ClosedBracket if depth == 0 => return,
+                TokenType::ClosedBracket => depth -= 1,
+                TokenType::Case | TokenType::Default if depth == 0 => return,
+                TokenType::Semicolon if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                _ => (),
+            }
+            self.advance();
+        }
+    }
+    // Skip to the next keyword that starts a top level declaration, ignoring
+    // any inside braces, like a var statement in a function body.
+    fn sync_decl(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.token.kind {
+                TokenType::EOF => return,
+                TokenType::OpenBracket => depth += 1,
+                // a stray } at the top level, skip it rather than go negative
+                TokenType::ClosedBracket => depth = (depth - 1).max(0),
+                TokenType::Func
+                | TokenType::Var
+                | TokenType::Const
+                | TokenType::Type
+                | TokenType::Import
+                    if depth == 0 =>
+                {
+                    return
+                }
+                _ => (),
+            }
+            self.advance();
+        }
+    }
+    // Span from `beg` up to the end of the last token we consumed
+    fn span_from(&self, beg: u32) -> Span {
+        // nothing consumed yet, like an empty statement
+        Span {
+            beg,
+            end: self.prev_end.max(beg),
+        }
+    }
+    fn peek_kind(&mut self) -> TokenType {
+        match self.reader.peek() {
+            Some(ts) => ts.token.kind,
+            None => TokenType::EOF,
+        }
+    }
+    fn parse_ident(&mut self) -> Result<Ident, ParseError> {
+        match self.token.kind {
+            TokenType::Ident | TokenType::Blank => {
+                let span = self.span;
+                Ok(Spanned::new(span, self.advance().value))
+            }
+            _ => Err(self.unexpected("identifier")),
+        }
+    }
+
+    // StatementList = { Statement ";" } .
+    // Ends at anything that closes the surrounding block or clause.
+    // A statement that fails to parse becomes Statement::Error and we pick up
+    // again at the next one.
+    fn parse_stmt_list(&mut self) -> Vec<Spanned<Statement>> {
+        let mut stmts = Vec::new();
+        while !matches!(
+            self.token.kind,
+            TokenType::ClosedBracket | TokenType::Case | TokenType::Default | TokenType::EOF
+        ) {
+            let beg = self.span.beg;
+            let stmt = match self.parse_stmt() {
+                Ok(stmt) => stmt,
+                Err(err) => {
+                    self.errors.push(err);
+                    self.sync_stmt();
+                    stmts.push(Spanned::new(self.span_from(beg), Statement::Error));
+                    continue;
+                }
+            };
+            stmts.push(stmt);
+            // the semicolon may be left out before a closing } on the same line
+            if self.token.kind != TokenType::ClosedBracket {
+                if let Err(err) = self.expect(TokenType::Semicolon) {
+                    self.errors.push(err);
+                    self.sync_stmt();
+                }
+            }
+        }
+        stmts
+    }
+    fn parse_block(&mut self) -> Result<Block, ParseError> {
+        self.expect(TokenType::OpenBracket)?;
+        // a function literal in a switch header has a body of its own
+        let tag = std::mem::replace(&mut self.type_switch_tag, false);
+        let stmts = self.parse_stmt_list();
+        self.type_switch_tag = tag;
+        self.expect(TokenType::ClosedBracket)?;
+        Ok(Block { stmts })
+    }
+    fn parse_stmt(&mut self) -> Result<Spanned<Statement>, ParseError> {
+        let beg = self.span.beg;
+        let kind = self.token.kind;
+        let stmt = match kind {
+            TokenType::Var | TokenType::Const | TokenType::Type => {
+                Statement::Decl(self.parse_decl()?)
+            }
+            TokenType::Ident if self.peek_kind() == TokenType::Colon => {
+                let label = self.parse_ident()?;
+                self.expect(TokenType::Colon)?;
+                let stmt = self.parse_stmt()?;
+                Statement::Labeled(LabeledStmt {
+                    label,
+                    stmt: Box::new(stmt),
+                })
+            }
+            TokenType::Return => {
+                self.advance();
+                let results = match self.token.kind {
+                    TokenType::Semicolon | TokenType::ClosedBracket => Vec::new(),
+                    _ => self.parse_expr_list()?,
+                };
+                Statement::Return(ReturnStmt { results })
+            }
+            TokenType::Break => {
+                self.advance();
+                let label = self.parse_label()?;
+                Statement::Break(BreakStmt { label })
+            }
+            TokenType::Continue => {
+                self.advance();
+                let label = self.parse_label()?;
+                Statement::Continue(ContinueStmt { label })
+            }
+            TokenType::Goto => {
+                self.advance();
+                let label = self.parse_ident()?;
+                Statement::Goto(GotoStmt { label })
+            }
+            TokenType::Fallthrough => {
+                self.advance();
+                Statement::Fallthrough(FallthroughStmt)
+            }
+            TokenType::OpenBracket => Statement::Block(self.parse_block()?),
+            TokenType::If => Statement::If(self.parse_if()?),
+            TokenType::Switch => self.parse_switch()?,
+            TokenType::For => Statement::For(self.parse_for()?),
+            TokenType::Select => Statement::Select(self.parse_select()?),
+            TokenType::Go => {
+                self.advance();
+                let call = self.parse_expr()?;
+                if !is_call(&call.item) {
+                    self.error("expression in go must be function call", call.span);
+                }
+                Statement::Go(GoStmt { call })
+            }
+            TokenType::Defer => {
+                self.advance();
+                let call = self.parse_expr()?;
+                if !is_call(&call.item) {
+                    self.error("expression in defer must be function call", call.span);
+                }
+                Statement::Defer(DeferStmt { call })
+            }
+            TokenType::Semicolon | TokenType::ClosedBracket => Statement::Empty(EmptyStmt),
+            _ => Statement::Simple(self.parse_simple_stmt()?),
+        };
+        Ok(Spanned::new(self.span_from(beg), stmt))
+    }
+    fn parse_label(&mut self) -> Result<Option<Ident>, ParseError> {
+        if self.token.kind == TokenType::Ident {
+            return Ok(Some(self.parse_ident()?));
+        }
+        Ok(None)
+    }
+    // SimpleStmt = EmptyStmt | ExpressionStmt | SendStmt | IncDecStmt | Assignment | ShortVarDecl .
+    // They all start with an expression (list), the token after it decides which one it is.
+    fn parse_simple_stmt(&mut self) -> Result<SimpleStmt, ParseError> {
+        let lhs = self.parse_expr_list()?;
+        self.parse_simple_stmt_rest(lhs)
+    }
+    fn parse_simple_stmt_rest(
+        &mut self,
+        mut lhs: Vec<Spanned<Expr>>,
+    ) -> Result<SimpleStmt, ParseError> {
+        let kind = self.token.kind;
+        match kind {
+            TokenType::ColonAssign => {
+                let names = idents_of(&lhs)?;
+                self.advance();
+                let values = self.parse_expr_list()?;
+                return Ok(SimpleStmt::ShortVarDecl(ShortVarDecl { names, values }));
+            }
+            TokenType::Assign => {
+                self.advance();
+                let rhs = self.parse_expr_list()?;
+                return Ok(SimpleStmt::Assignment(Assignment { lhs, rhs, op: None }));
+            }
+            _ => (),
+        }
+        if let Some(op) = BinaryOperator::from_token_kind_assign_op(kind) {
+            self.advance();
+            let rhs = self.parse_expr_list()?;
+            return Ok(SimpleStmt::Assignment(Assignment {
+                lhs,
+                rhs,
+                op: Some(op),
+            }));
+        }
+        if lhs.len() > 1 {
+            return Err(self.unexpected_token(&[
+                TokenType::ColonAssign,
+                TokenType::Assign,
+                TokenType::Comma,
+            ]));
+        }
+        let expr = lhs.pop().unwrap();
+        Ok(match kind {
+            TokenType::Arrow => {
+                self.advance();
+                let value = self.parse_expr()?;
+                SimpleStmt::Send(SendStmt {
+                    channel: expr,
+                    value,
+                })
+            }
+            TokenType::Increment | TokenType::Decrement => {
+                self.advance();
+                SimpleStmt::IncDec(IncDecStmt {
+                    expr,
+                    inc: kind == TokenType::Increment,
+                })
+            }
+            _ => SimpleStmt::Expr(expr),
+        })
+    }
+    // IfStmt = "if" [ SimpleStmt ";" ] Expression Block [ "else" ( IfStmt | Block ) ] .
+    fn parse_if(&mut self) -> Result<IfStmt, ParseError> {
+        self.expect(TokenType::If)?;
+        let outer = self.expr_lev;
+        self.expr_lev = -1;
+        let header = self.parse_if_header();
+        self.expr_lev = outer;
+        let (init, cond) = header?;
+        let then = self.parse_block()?;
+        let els = if self.eat(TokenType::Else) {
+            match self.token.kind {
+                TokenType::If => Some(Box::new(Else::If(self.parse_if()?))),
+                TokenType::OpenBracket => Some(Box::new(Else::Block(self.parse_block()?))),
+                _ => return Err(self.unexpected_token(&[TokenType::If, TokenType::OpenBracket])),
+            }
+        } else {
+            None
+        };
+        Ok(IfStmt {
+            init,
+            cond,
+            then,
+            els,
+        })
+    }
+    fn parse_if_header(&mut self) -> Result<(Option<SimpleStmt>, Spanned<Expr>), ParseError> {
+        let mut init = None;
+        if self.token.kind != TokenType::Semicolon {
+            init = Some(self.parse_simple_stmt()?);
+        }
+        if !self.eat(TokenType::Semicolon) {
+            return match init {
+                Some(SimpleStmt::Expr(cond)) => Ok((None, cond)),
+                _ => Err(self.unexpected("if condition")),
+            };
+        }
+        let cond = self.parse_expr()?;
+        Ok((init, cond))
+    }
+    // SwitchStmt = "switch" [ SimpleStmt ";" ] [ Expression ] "{" { CaseClause } "}" .
+    // A type switch looks the same up to the `{`, what tells them apart is the
+    // x.(type) guard in the tag position.
+    fn parse_switch(&mut self) -> Result<Statement, ParseError> {
+        let beg = self.span.beg;
+        self.expect(TokenType::Switch)?;
+        let outer = self.expr_lev;
+        self.expr_lev = -1;
+        let header = self.parse_switch_header();
+        self.expr_lev = outer;
+        let (init, tag) = header?;
+        let guard = match &tag {
+            Some(tag) => type_switch_guard(tag),
+            None => None,
+        };
+        if let Some((binding, expr)) = guard {
+            let clauses = self.parse_case_clauses(|p| {
+                let mut types = vec![p.parse_type()?];
+                while p.eat(TokenType::Comma) {
+                    types.push(p.parse_type()?);
+                }
+                Ok(types)
+            })?;
+            return Ok(Statement::TypeSwitch(TypeSwitchStmt {
+                init,
+                binding,
+                expr,
+                clauses,
+            }));
+        }
+        let tag = match tag {
+            Some(SimpleStmt::Expr(expr)) => Some(expr),
+            None => None,
+            Some(_) => {
+                return Err(ParseError::invalid(
+                    "switch expression must be an expression",
+                    self.span_from(beg),
+                ))
+            }
+        };
+        let clauses = self.parse_case_clauses(|p| p.parse_expr_list())?;
+        Ok(Statement::Switch(SwitchStmt { init, tag, clauses }))
+    }
+    fn parse_switch_header(
+        &mut self,
+    ) -> Result<(Option<SimpleStmt>, Option<SimpleStmt>), ParseError> {
+        let mut init = None;
+        let mut tag = None;
+        if self.token.kind == TokenType::OpenBracket {
+            return Ok((init, tag));
+        }
+        if self.token.kind != TokenType::Semicolon {
+            tag = Some(self.parse_switch_tag()?);
+        }
+        if self.eat(TokenType::Semicolon) {
+            init = tag.take();
+            if self.token.kind != TokenType::OpenBracket {
+                tag = Some(self.parse_switch_tag()?);
+            }
+        }
+        Ok((init, tag))
+    }
+    // Until the `;` we don't know if the statement is the init or the tag, so
+    // both may hold the guard. parse_postfix only takes it right before the `{`.
+    fn parse_switch_tag(&mut self) -> Result<SimpleStmt, ParseError> {
+        self.type_switch_tag = true;
+        let stmt = self.parse_simple_stmt();
+        self.type_switch_tag = false;
+        stmt
+    }
+    // The clauses of expression and type switches only differ in what comes
+    // after `case`, which `parse_case` takes care of.
+    fn parse_case_clauses<T>(
+        &mut self,
+        parse_case: impl Fn(&mut Self) -> Result<Vec<T>, ParseError>,
+    ) -> Result<Vec<CaseClause<T>>, ParseError> {
+        self.expect(TokenType::OpenBracket)?;
+        let mut clauses = Vec::new();
+        while self.token.kind != TokenType::ClosedBracket {
+            let case = match self.token.kind {
+                TokenType::Case => {
+                    self.advance();
+                    Some(parse_case(self)?)
+                }
+                TokenType::Default => {
+                    self.advance();
+                    None
+                }
+                _ => return Err(self.unexpected_token(&[TokenType::Case, TokenType::Default])),
+            };
+            self.expect(TokenType::Colon)?;
+            let body = self.parse_stmt_list();
+            clauses.push(CaseClause { case, body });
+        }
+        self.expect(TokenType::ClosedBracket)?;
+        Ok(clauses)
+    }
+    // ForStmt = "for" [ Condition | ForClause | RangeClause ] Block .
+    // `for {}` is a ForClause with all three parts left out.
+    fn parse_for(&mut self) -> Result<ForStmt, ParseError> {
+        self.expect(TokenType::For)?;
+        let outer = self.expr_lev;
+        self.expr_lev = -1;
+        let header = self.parse_for_header();
+        self.expr_lev = outer;
+        let header = header?;
+        let body = self.parse_block()?;
+        Ok(ForStmt { header, body })
+    }
+    fn parse_for_header(&mut self) -> Result<ForHeader, ParseError> {
+        let mut init = None;
+        match self.token.kind {
+            TokenType::OpenBracket => {
+                return Ok(ForHeader::ForClause(ForClause {
+                    init: None,
+                    condition: None,
+                    post: None,
+                }))
+            }
+            TokenType::Range => {
+                self.advance();
+                let expr = self.parse_expr()?;
+                return Ok(ForHeader::Range(RangeClause { vars: None, expr }));
+            }
+            TokenType::Semicolon => (),
+            _ => {
+                let lhs = self.parse_expr_list()?;
+                let define = self.token.kind == TokenType::ColonAssign;
+                if (define || self.token.kind == TokenType::Assign)
+                    && self.peek_kind() == TokenType::Range
+                {
+                    self.advance();
+                    self.advance();
+                    let vars = if define {
+                        IterVars::Idents(idents_of(&lhs)?)
+                    } else {
+                        IterVars::Exprs(lhs)
+                    };
+                    let expr = self.parse_expr()?;
+                    return Ok(ForHeader::Range(RangeClause {
+                        vars: Some(vars),
+                        expr,
+                    }));
+                }
+                init = Some(self.parse_simple_stmt_rest(lhs)?);
+            }
+        }
+        if self.token.kind != TokenType::Semicolon {
+            return match init {
+                Some(SimpleStmt::Expr(cond)) => Ok(ForHeader::Condition(cond)),
+                _ => Err(self.unexpected("for loop condition")),
+            };
+        }
+        self.advance();
+        let condition = match self.token.kind {
+            TokenType::Semicolon => None,
+            _ => Some(self.parse_expr()?),
+        };
+        self.expect(TokenType::Semicolon)?;
+        let post = match self.token.kind {
+            TokenType::OpenBracket => None,
+            _ => {
+                let beg = self.span.beg;
+                let post = self.parse_simple_stmt()?;
+                if let SimpleStmt::ShortVarDecl(_) = post {
+                    let span = self.span_from(beg);
+                    self.error("cannot declare in post statement of for loop", span);
+                }
+                Some(post)
+            }
+        };
+        Ok(ForHeader::ForClause(ForClause {
+            init,
+            condition,
+            post,
+        }))
+    }
+    // SelectStmt = "select" "{" { CommClause } "}" .
+    // CommCase   = "case" ( SendStmt | RecvStmt ) | "default" .
+    fn parse_select(&mut self) -> Result<SelectStmt, ParseError> {
+        self.expect(TokenType::Select)?;
+        self.expect(TokenType::OpenBracket)?;
+        let mut clauses = Vec::new();
+        while self.token.kind != TokenType::ClosedBracket {
+            let case = match self.token.kind {
+                TokenType::Case => {
+                    self.advance();
+                    let beg = self.span.beg;
+                    match comm_case_of(self.parse_simple_stmt()?) {
+                        Some(case) => case,
+                        None => {
+                            let span = self.span_from(beg);
+                            self.error("select case must be receive, send or assign recv", span);
+                            CommCase::Error
+                        }
+                    }
+                }
+                TokenType::Default => {
+                    self.advance();
+                    CommCase::Default
+                }
+                _ => return Err(self.unexpected_token(&[TokenType::Case, TokenType::Default])),
+            };
+            self.expect(TokenType::Colon)?;
+            let body = self.parse_stmt_list();
+            clauses.push(CommClause { case, body });
+        }
+        self.expect(TokenType::ClosedBracket)?;
+        Ok(SelectStmt { clauses })
+    }
+    // TopLevelDecl = Declaration | FunctionDecl | MethodDecl .
+    fn parse_top_level_decl(&mut self) -> Result<Spanned<TopLevelDecl>, ParseError> {
+        let beg = self.span.beg;
+        let decl = match self.token.kind {
+            TokenType::Func => self.parse_func_decl()?,
+            TokenType::Var | TokenType::Const | TokenType::Type => {
+                TopLevelDecl::Decl(self.parse_decl()?)
+            }
+            _ => return Err(self.unexpected("declaration")),
+        };
+        Ok(Spanned::new(self.span_from(beg), decl))
+    }
+    // FunctionDecl = "func" FunctionName Signature [ FunctionBody ] .
+    // MethodDecl   = "func" Receiver MethodName Signature [ FunctionBody ] .
+    fn parse_func_decl(&mut self) -> Result<TopLevelDecl, ParseError> {
+        self.expect(TokenType::Func)?;
+        let receiver = if self.token.kind == TokenType::OpenParen {
+            let beg = self.span.beg;
+            let mut params = self.parse_params()?;
+            if params.is_empty() {
+                self.error("method has no receiver", self.span_from(beg));
+            } else if params.len() > 1 || params[0].names.len() > 1 || params[0].variadic {
+                // go on with the first one
+                self.error("method has multiple receivers", self.span_from(beg));
+                params.truncate(1);
+                params[0].names.truncate(1);
+                params[0].variadic = false;
+            }
+            params.pop()
+        } else {
+            None
+        };
+        let name = self.parse_ident()?;
+        let mut type_params = Vec::new();
+        if self.token.kind == TokenType::OpenSquare {
+            let beg = self.span.beg;
+            type_params = self.parse_type_params()?;
+            if receiver.is_some() {
+                let span = self.span_from(beg);
+                self.error("method must have no type parameters", span);
+            }
+        }
+        let signature = self.parse_signature()?;
+        let body = match self.token.kind {
+            TokenType::OpenBracket => Some(self.parse_block()?),
+            _ => None,
+        };
+        Ok(match receiver {
+            Some(receiver) => TopLevelDecl::Method(MethodDecl {
+                receiver,
+                name,
+                signature,
+                body,
+            }),
+            None => TopLevelDecl::Func(FuncDecl {
+                name,
+                type_params,
+                signature,
+                body,
+            }),
+        })
+    }
+    // Signature = Parameters [ Result ] .
+    // Result    = Parameters | Type .
+    fn parse_signature(&mut self) -> Result<Signature, ParseError> {
+        let params = self.parse_params()?;
+        let results = match self.token.kind {
+            TokenType::OpenParen => self.parse_params()?,
+            kind if starts_type(kind) => vec![ParamDecl {
+                names: Vec::new(),
+                typ: self.parse_type()?,
+                variadic: false,
+            }],
+            _ => Vec::new(),
+        };
+        Ok(Signature { params, results })
+    }
+    // Parameters = "(" [ ParameterList [ "," ] ] ")" .
+    // Either every parameter has a name or none of them do, and we can't tell
+    // which until we have seen the whole list: in (a, b int) a and b are names,
+    // in (a, b) they are types. So collect the entries first and sort it out after.
+    fn parse_params(&mut self) -> Result<Vec<ParamDecl>, ParseError> {
+        let beg = self.span.beg;
+        self.expect(TokenType::OpenParen)?;
+        // (name, type, variadic), at least one of name and type is set
+        let mut entries: Vec<(Option<Ident>, Option<Type>, bool)> = Vec::new();
+        while self.token.kind != TokenType::ClosedParen {
+            let mut name = None;
+            let mut typ = None;
+            if self.token.kind == TokenType::Ident {
+                let ident = self.parse_ident()?;
+                match self.token.kind {
+                    TokenType::Comma | TokenType::ClosedParen => name = Some(ident),
+                    TokenType::Period => {
+                        self.advance();
+                        let type_name = TypeName {
+                            package: Some(ident),
+                            name: self.parse_ident()?,
+                        };
+                        typ = Some(self.parse_type_args_of(type_name)?);
+                    }
+                    TokenType::OpenSquare => {
+                        let (ident, bracketed) = self.parse_name_or_instance(ident)?;
+                        name = ident;
+                        typ = Some(bracketed);
+                    }
+                    _ => name = Some(ident),
+                }
+            }
+            let mut variadic = false;
+            if typ.is_none()
+                && !matches!(self.token.kind, TokenType::Comma | TokenType::ClosedParen)
+            {
+                variadic = self.eat(TokenType::Ellipsis);
+                typ = Some(self.parse_type()?);
+            }
+            // like the empty entry in (,)
+            if name.is_none() && typ.is_none() {
+                return Err(self.unexpected("parameter"));
+            }
+            entries.push((name, typ, variadic));
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        self.expect(TokenType::ClosedParen)?;
+
+        let named = entries
+            .iter()
+            .any(|(name, typ, _)| name.is_some() && typ.is_some());
+        let mut params = Vec::new();
+        if !named {
+            // a lone identifier is a type name
+            for (name, typ, variadic) in entries {
+                let typ = match (name, typ) {
+                    (_, Some(typ)) => typ,
+                    (Some(name), None) => Type::Name(TypeName {
+                        package: None,
+                        name,
+                    }),
+                    // never pushed, see above
+                    (None, None) => continue,
+                };
+                params.push(ParamDecl {
+                    names: Vec::new(),
+                    typ,
+                    variadic,
+                });
+            }
+        } else {
+            // names without a type share the type of the next parameter that has one
+            let mut names = Vec::new();
+            for (name, typ, variadic) in entries {
+                match (name, typ) {
+                    (Some(name), typ) => {
+                        names.push(name);
+                        if let Some(typ) = typ {
+                            params.push(ParamDecl {
+                                names: std::mem::take(&mut names),
+                                typ,
+                                variadic,
+                            });
+                        }
+                    }
+                    (None, _) => {
+                        return Err(ParseError::invalid(
+                            "mixed named and unnamed parameters",
+                            self.span_from(beg),
+                        ))
+                    }
+                }
+            }
+            if !names.is_empty() {
+                return Err(ParseError::invalid(
+                    "missing parameter type",
+                    self.span_from(beg),
+                ));
+            }
+        }
+        if let Some(pos) = params.iter().position(|p| p.variadic) {
+            if pos != params.len() - 1 || params[pos].names.len() > 1 {
+                let span = self.span_from(beg);
+                self.error("can only use ... with final parameter in list", span);
+            }
+        }
+        Ok(params)
+    }
+    // Declaration = ConstDecl | TypeDecl | VarDecl .
+    fn parse_decl(&mut self) -> Result<DeclStmt, ParseError> {
+        match self.advance().kind {
+            TokenType::Const => {
+                let mut specs: Vec<ConstSpec> = Vec::new();
+                self.parse_group(|p| {
+                    let spec = p.parse_const_spec(specs.len() as u32, specs.last())?;
+                    specs.push(spec);
+                    Ok(())
+                })?;
+                Ok(DeclStmt::Const(ConstDecl { specs }))
+            }
+            TokenType::Var => {
+                let mut specs = Vec::new();
+                self.parse_group(|p| {
+                    specs.push(p.parse_var_spec()?);
+                    Ok(())
+                })?;
+                Ok(DeclStmt::VarDecl(VarDecl { specs }))
+            }
+            _ => {
+                let mut specs = Vec::new();
+                self.parse_group(|p| {
+                    specs.push(p.parse_type_spec()?);
+                    Ok(())
+                })?;
+                Ok(DeclStmt::TypeDecl(TypeDecl { specs }))
+            }
+        }
+    }
+    // TypeSpec = identifier [ TypeParameters ] [ "=" ] Type .
+    // type T[P any] ... and type A [N]int start out the same, it is the token
+    // after the first name in the brackets that tells them apart. As in Go,
+    // type T[P *C] reads as an array, a pointer constraint has to be written
+    // as [P interface{ *C }].
+    fn parse_type_spec(&mut self) -> Result<TypeSpec, ParseError> {
+        let name = self.parse_ident()?;
+        let mut type_params = Vec::new();
+        if self.eat(TokenType::OpenSquare) {
+            if !matches!(self.token.kind, TokenType::Ident | TokenType::Blank)
+                || !starts_constraint(self.peek_kind())
+            {
+                let typ = self.parse_array_or_slice()?;
+                return Ok(TypeSpec {
+                    name,
+                    type_params,
+                    alias: false,
+                    typ,
+                });
+            }
+            type_params = self.parse_type_param_list()?;
+        }
+        let alias = self.eat(TokenType::Assign);
+        let typ = self.parse_type()?;
+        Ok(TypeSpec {
+            name,
+            type_params,
+            alias,
+            typ,
+        })
+    }
+    // TypeParameters = "[" TypeParamList [ "," ] "]" .
+    fn parse_type_params(&mut self) -> Result<Vec<TypeParamDecl>, ParseError> {
+        self.expect(TokenType::OpenSquare)?;
+        self.parse_type_param_list()
+    }
+    // The rest of the type parameters after the `[`.
+    // TypeParamDecl = IdentifierList TypeConstraint .
+    fn parse_type_param_list(&mut self) -> Result<Vec<TypeParamDecl>, ParseError> {
+        let beg = self.prev_end;
+        let mut params = Vec::new();
+        while self.token.kind != TokenType::ClosedSquare {
+            let mut names = vec![self.parse_ident()?];
+            while self.eat(TokenType::Comma) {
+                names.push(self.parse_ident()?);
+            }
+            let constraint = self.parse_type_elem()?;
+            params.push(TypeParamDecl { names, constraint });
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        self.expect(TokenType::ClosedSquare)?;
+        if params.is_empty() {
+            let span = self.span_from(beg);
+            self.error("empty type parameter list", span);
+        }
+        Ok(params)
+    }
+    // Either a single spec or "(" { Spec ";" } ")"
+    fn parse_group(
+        &mut self,
+        mut parse_spec: impl FnMut(&mut Self) -> Result<(), ParseError>,
+    ) -> Result<(), ParseError> {
+        if !self.eat(TokenType::OpenParen) {
+            return parse_spec(self);
+        }
+        while self.token.kind != TokenType::ClosedParen {
+            parse_spec(self)?;
+            if self.token.kind != TokenType::ClosedParen {
+                self.expect(TokenType::Semicolon)?;
+            }
+        }
+        self.expect(TokenType::ClosedParen)?;
+        Ok(())
+    }
+    // ConstSpec = IdentifierList [ [ Type ] "=" ExpressionList ] .
+    fn parse_const_spec(
+        &mut self,
+        iota: u32,
+        prev: Option<&ConstSpec>,
+    ) -> Result<ConstSpec, ParseError> {
+        let beg = self.span.beg;
+        // same syntax as a var spec, the rules for what may be left out differ
+        let VarSpec {
+            names,
+            mut typ,
+            mut values,
+        } = self.parse_value_spec()?;
+        if values.is_empty() {
+            let span = self.span_from(beg);
+            // implicit repetition, like the B and C in const ( A = iota; B; C )
+            match prev {
+                _ if typ.is_some() => self.error(
+                    "const declaration cannot have type without expression",
+                    span,
+                ),
+                Some(prev) => {
+                    typ = prev.typ.clone();
+                    values = prev.values.clone();
+                }
+                None => self.error("missing init expr for const declaration", span),
+            }
+        }
+        Ok(ConstSpec {
+            names,
+            typ,
+            values,
+            iota,
+        })
+    }
+    // VarSpec = IdentifierList ( Type [ "=" ExpressionList ] | "=" ExpressionList ) .
+    fn parse_var_spec(&mut self) -> Result<VarSpec, ParseError> {
+        let beg = self.span.beg;
+        let spec = self.parse_value_spec()?;
+        if spec.typ.is_none() && spec.values.is_empty() {
+            let span = self.span_from(beg);
+            self.error("missing variable type or initialization", span);
+        }
+        Ok(spec)
+    }
+    fn parse_value_spec(&mut self) -> Result<VarSpec, ParseError> {
+        let mut names = vec![self.parse_ident()?];
+        while self.eat(TokenType::Comma) {
+            names.push(self.parse_ident()?);
+        }
+        let typ = match self.token.kind {
+            TokenType::Assign | TokenType::Semicolon | TokenType::ClosedParen => None,
+            _ => Some(self.parse_type()?),
+        };
+        let values = if self.eat(TokenType::Assign) {
+            self.parse_expr_list()?
+        } else {
+            Vec::new()
+        };
+        Ok(VarSpec { names, typ, values })
+    }
+
+    // Precedence climbing: parse a unary expression, then keep folding in binary
+    // operators that bind at least as tightly as `min_prec`. The right hand side is
+    // parsed with `prec + 1`, so an operator of the same precedence ends up as the
+    // parent rather than the child, which is what makes a - b - c mean (a - b) - c.
+    pub fn parse_expr(&mut self) -> Result<Spanned<Expr>, ParseError> {
+        self.parse_binary_expr(1)
+    }
+    fn parse_binary_expr(&mut self, min_prec: i32) -> Result<Spanned<Expr>, ParseError> {
+        let unary = self.parse_unary_expr()?;
+        let lhs = Spanned::new(unary.span, Expr::Unary(unary.item));
+        self.parse_binary_rhs(lhs, min_prec)
+    }
+    // Carries on from an already parsed left hand side
+    fn parse_binary_rhs(
+        &mut self,
+        mut lhs: Spanned<Expr>,
+        min_prec: i32,
+    ) -> Result<Spanned<Expr>, ParseError> {
+        while let Some(op) = BinaryOperator::from_token_kind(self.token.kind) {
+            let prec = op.precedence();
+            if prec < min_prec {
+                break;
+            }
+            self.advance();
+            self.type_switch_tag = false;
+            let rhs = self.parse_binary_expr(prec + 1)?;
+            let span = Span {
+                beg: lhs.span.beg,
+                end: rhs.span.end,
+            };
+            let expr = BinaryExpr {
+                lhs: Box::new(lhs),
+                op,
+                rhs: Box::new(rhs),
+            };
+            lhs = Spanned::new(span, Expr::Binary(expr));
+        }
+        Ok(lhs)
+    }
+    // Unary operators are prefixes and bind tighter than any binary operator,
+    // so -a * b is (-a) * b.
+    fn parse_unary_expr(&mut self) -> Result<Spanned<UnaryExpr>, ParseError> {
+        let beg = self.span.beg;
+        if let Some(operator) = UnaryOperator::from_token_kind(self.token.kind) {
+            self.advance();
+            self.type_switch_tag = false;
+            let operand = self.parse_unary_expr()?;
+            let operation = UnaryOperation {
+                operator,
+                operand: Box::new(operand),
+            };
+            return Ok(Spanned::new(
+                self.span_from(beg),
+                UnaryExpr::UnaryOperation(operation),
+            ));
+        }
+        let primary = self.parse_primary_expr()?;
+        Ok(Spanned::new(
+            primary.span,
+            UnaryExpr::Primary(Box::new(primary.item)),
+        ))
+    }
+    fn parse_expr_list(&mut self) -> Result<Vec<Spanned<Expr>>, ParseError> {
+        let mut list = vec![self.parse_expr()?];
+        while self.eat(TokenType::Comma) {
+            list.push(self.parse_expr()?);
+        }
+        Ok(list)
+    }
+    fn parse_primary_expr(&mut self) -> Result<Spanned<PrimaryExpr>, ParseError> {
+        let beg = self.span.beg;
+        let primary = if self.token.kind == TokenType::Func {
+            self.parse_func_lit()?
+        } else if starts_type_literal(self.token.kind) {
+            let typ = self.parse_type()?;
+            self.parse_type_operand(beg, typ)?
+        } else {
+            let operand = self.parse_operand()?;
+            Spanned::new(operand.span, PrimaryExpr::Operand(operand.item))
+        };
+        self.parse_postfix(primary)
+    }
+    // FunctionLit = "func" Signature FunctionBody .
+    // Without a body it is a func type, which can still be converted to.
+    fn parse_func_lit(&mut self) -> Result<Spanned<PrimaryExpr>, ParseError> {
+        let beg = self.span.beg;
+        self.expect(TokenType::Func)?;
+        let signature = self.parse_signature()?;
+        if self.token.kind != TokenType::OpenBracket {
+            return self.parse_type_operand(beg, Type::Func(signature));
+        }
+        // the body is a block of its own, even inside an if or for header
+        self.expr_lev += 1;
+        let body = self.parse_block();
+        self.expr_lev -= 1;
+        let lit = FuncLit {
+            signature,
+            body: body?,
+        };
+        Ok(Spanned::new(
+            self.span_from(beg),
+            PrimaryExpr::Operand(Operand::FuncLit(lit)),
+        ))
+    }
+    // An expression that starts with a type literal, either a composite literal
+    // like []int{1, 2} or a conversion like []byte(s). For a plain T{} or T(x)
+    // we only find out it was a type once we get to the { or (, see parse_postfix.
+    fn parse_type_operand(
+        &mut self,
+        beg: u32,
+        typ: Type,
+    ) -> Result<Spanned<PrimaryExpr>, ParseError> {
+        if self.token.kind == TokenType::OpenBracket {
+            let value = self.parse_literal_value()?;
+            let lit = CompositeLit { typ, value };
+            return Ok(Spanned::new(
+                self.span_from(beg),
+                PrimaryExpr::Operand(Operand::CompositeLit(lit)),
+            ));
+        }
+        if self.token.kind != TokenType::OpenParen {
+            return Err(self.unexpected_token(&[TokenType::OpenParen, TokenType::OpenBracket]));
+        }
+        self.advance();
+        self.expr_lev += 1;
+        let expr = self.parse_expr();
+        self.expr_lev -= 1;
+        let expr = expr?;
+        self.eat(TokenType::Comma);
+        self.expect(TokenType::ClosedParen)?;
+        let conversion = Conversion {
+            typ,
+            expr: Box::new(expr),
+        };
+        Ok(Spanned::new(
+            self.span_from(beg),
+            PrimaryExpr::Conversion(conversion),
+        ))
+    }
+    // Selectors, indexes, slices, type assertions and calls all hang off the end
+    // of a primary expression and chain left to right: a.b[i](x) is a call of
+    // an index of a selector.
+    fn parse_postfix(
+        &mut self,
+        mut primary: Spanned<PrimaryExpr>,
+    ) -> Result<Spanned<PrimaryExpr>, ParseError> {
+        let beg = primary.span.beg;
+        loop {
+            let node = match self.token.kind {
+                // T{...} is a composite literal, unless we are in the header of an if,
+                // for or switch where the { starts the body. Put it in parens there.
+                TokenType::OpenBracket if self.expr_lev >= 0 => {
+                    match type_of_primary(&primary.item) {
+                        Some(typ) => {
+                            let value = self.parse_literal_value()?;
+                            PrimaryExpr::Operand(Operand::CompositeLit(CompositeLit { typ, value }))
+                        }
+                        None => return Ok(primary),
+                    }
+                }
+                TokenType::Period => {
+                    self.advance();
+                    if self.eat(TokenType::OpenParen) {
+                        // x.(type) is only valid as the guard of a type switch, typ is None for it
+                        let typ = if self.eat(TokenType::Type) {
+                            None
+                        } else {
+                            Some(self.parse_type()?)
+                        };
+                        self.expect(TokenType::ClosedParen)?;
+                        if typ.is_none()
+                            && !(self.type_switch_tag && self.token.kind == TokenType::OpenBracket)
+                        {
+                            let span = self.span_from(beg);
+                            self.error("use of .(type) outside type switch", span);
+                        }
+                        PrimaryExpr::TypeAssertion(TypeAssertion {
+                            expr: Box::new(primary),
+                            typ,
+                        })
+                    } else {
+                        let selector = self.parse_ident()?;
+                        PrimaryExpr::SelectorExpr(SelectorExpr {
+                            operand: Box::new(primary),
+                            selector,
+                        })
+                    }
+                }
+                TokenType::OpenSquare => {
+                    self.expr_lev += 1;
+                    let node = self.parse_index_or_slice(primary);
+                    self.expr_lev -= 1;
+                    node?
+                }
+                TokenType::OpenParen => {
+                    self.expr_lev += 1;
+                    let args = self.parse_arguments();
+                    self.expr_lev -= 1;
+                    let args = args?;
+                    PrimaryExpr::FuncCall(FuncCall {
+                        callee: Box::new(primary),
+                        args,
+                    })
+                }
+                _ => return Ok(primary),
+            };
+            primary = Spanned::new(self.span_from(beg), node);
+        }
+    }
+    // LiteralValue = "{" [ ElementList [ "," ] ] "}" .
+    fn parse_literal_value(&mut self) -> Result<Spanned<LiteralValue>, ParseError> {
+        let beg = self.span.beg;
+        self.expect(TokenType::OpenBracket)?;
+        self.expr_lev += 1;
+        let elems = self.parse_element_list();
+        self.expr_lev -= 1;
+        let elems = elems?;
+        self.expect(TokenType::ClosedBracket)?;
+        Ok(Spanned::new(self.span_from(beg), LiteralValue { elems }))
+    }
+    // KeyedElement = [ Key ":" ] Element .
+    fn parse_element_list(&mut self) -> Result<Vec<KeyedElement>, ParseError> {
+        let mut elems = Vec::new();
+        while self.token.kind != TokenType::ClosedBracket {
+            let mut key = None;
+            let mut value = self.parse_element()?;
+            if self.eat(TokenType::Colon) {
+                key = Some(value);
+                value = self.parse_element()?;
+            }
+            elems.push(KeyedElement { key, value });
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        Ok(elems)
+    }
+    // A nested { ... } is a composite literal with its type left out,
+    // the type comes from the enclosing literal.
+    fn parse_element(&mut self) -> Result<Element, ParseError> {
+        if self.token.kind == TokenType::OpenBracket {
+            return Ok(Element::Lit(self.parse_literal_value()?));
+        }
+        Ok(Element::Expr(self.parse_expr()?))
+    }
+    // a[i], a[lo:hi] or a[lo:hi:max], any of lo and hi may be left out
+    // but a 3-index slice needs both hi and max.
+    fn parse_index_or_slice(
+        &mut self,
+        operand: Spanned<PrimaryExpr>,
+    ) -> Result<PrimaryExpr, ParseError> {
+        self.expect(TokenType::OpenSquare)?;
+        let low = if self.token.kind != TokenType::Colon {
+            match self.parse_type_or_expr()? {
+                TypeOrExpr::Expr(index) if self.eat(TokenType::ClosedSquare) => {
+                    // F[int] or a[i], only what F or a is can tell
+                    if type_name_of(&operand.item).is_some() {
+                        if let Some(typ) = type_of_expr(&index.item) {
+                            return Ok(PrimaryExpr::IndexOrInstance(IndexOrInstance {
+                                operand: Box::new(operand),
+                                index,
+                                typ,
+                            }));
+                        }
+                    }
+                    return Ok(PrimaryExpr::Indexing(IndexExpr {
+                        operand: Box::new(operand),
+                        index,
+                    }));
+                }
+                TypeOrExpr::Expr(low) if self.token.kind != TokenType::Comma => Some(low),
+                // a type, or more than one of them, F[[]int] or F[K, V]
+                first => {
+                    let mut args = vec![first.into_type()?];
+                    while self.eat(TokenType::Comma) && self.token.kind != TokenType::ClosedSquare {
+                        args.push(self.parse_type_or_expr()?.into_type()?);
+                    }
+                    self.expect(TokenType::ClosedSquare)?;
+                    return Ok(PrimaryExpr::Instantiation(Instantiation {
+                        operand: Box::new(operand),
+                        args,
+                    }));
+                }
+            }
+        } else {
+            None
+        };
+        self.expect(TokenType::Colon)?;
+        let high = match self.token.kind {
+            TokenType::Colon | TokenType::ClosedSquare => None,
+            _ => Some(self.parse_expr()?),
+        };
+        let mut max = None;
+        if self.token.kind == TokenType::Colon {
+            if high.is_none() {
+                self.error("middle index required in 3-index slice", self.span);
+            }
+            self.advance();
+            max = Some(self.parse_expr()?);
+        }
+        self.expect(TokenType::ClosedSquare)?;
+        Ok(PrimaryExpr::Slicing(SliceExpr {
+            operand: Box::new(operand),
+            slicing: Slicing { low, high, max },
+        }))
+    }
+    // Where a type can go as well as an expression, like in a type argument.
+    // A type literal could still turn out to be the start of an expression,
+    // []byte(s) or []int{1, 2}[0].
+    fn parse_type_or_expr(&mut self) -> Result<TypeOrExpr, ParseError> {
+        if !self.at_type_literal() {
+            return Ok(TypeOrExpr::Expr(self.parse_expr()?));
+        }
+        let beg = self.span.beg;
+        let typ = self.parse_type()?;
+        if !matches!(
+            self.token.kind,
+            TokenType::OpenParen | TokenType::OpenBracket
+        ) {
+            return Ok(TypeOrExpr::Type(typ));
+        }
+        let primary = self.parse_type_operand(beg, typ)?;
+        let primary = self.parse_postfix(primary)?;
+        let lhs = Spanned::new(
+            primary.span,
+            Expr::Unary(UnaryExpr::Primary(Box::new(primary.item))),
+        );
+        Ok(TypeOrExpr::Expr(self.parse_binary_rhs(lhs, 1)?))
+    }
+    // Like starts_type_literal, but also taking <-chan T for a type rather than a
+    // receive. Only where a type can go: elsewhere <-chan int(c) receives from
+    // a conversion, as the spec says.
+    fn at_type_literal(&mut self) -> bool {
+        starts_type_literal(self.token.kind)
+            || self.token.kind == TokenType::Arrow && self.peek_kind() == TokenType::Chan
+    }
+    fn parse_type_or_expr_list(&mut self) -> Result<Vec<TypeOrExpr>, ParseError> {
+        let mut list = vec![self.parse_type_or_expr()?];
+        while self.eat(TokenType::Comma) && self.token.kind != TokenType::ClosedSquare {
+            list.push(self.parse_type_or_expr()?);
+        }
+        Ok(list)
+    }
+    // Arguments = "(" [ ( ExpressionList | Type [ "," ExpressionList ] ) [ "..." ] [ "," ] ] ")" .
+    // The leading type is for builtins like make([]int, n).
+    fn parse_arguments(&mut self) -> Result<Arguments, ParseError> {
+        self.expect(TokenType::OpenParen)?;
+        let mut args = Arguments {
+            typ: None,
+            exprs: Vec::new(),
+            spread: false,
+        };
+        // where the last `...` was, until we know whether more arguments follow it
+        let mut ellipsis = None;
+        while self.token.kind != TokenType::ClosedParen {
+            if let Some(span) = ellipsis.take() {
+                self.error("can only use ... with final argument in list", span);
+            }
+            // make([]int, 1) vs f([]byte(s))
+            let expr = if args.exprs.is_empty() && args.typ.is_none() && self.at_type_literal() {
+                match self.parse_type_or_expr()? {
+                    TypeOrExpr::Type(typ) => {
+                        args.typ = Some(typ);
+                        if !self.eat(TokenType::Comma) {
+                            break;
+                        }
+                        continue;
+                    }
+                    TypeOrExpr::Expr(expr) => expr,
+                }
             } else {
-                self.token = Token {
-                    kind: TokenType::EOF,
-                    value: "none".to_string(),
+                self.parse_expr()?
+            };
+            args.exprs.push(expr);
+            if self.token.kind == TokenType::Ellipsis {
+                ellipsis = Some(self.span);
+                self.advance();
+                args.spread = true;
+            }
+            if !self.eat(TokenType::Comma) {
+                break;
+            }
+        }
+        self.expect(TokenType::ClosedParen)?;
+        Ok(args)
+    }
+    fn parse_operand(&mut self) -> Result<Spanned<Operand>, ParseError> {
+        let beg = self.span.beg;
+        let operand = match self.token.kind {
+            TokenType::Ident | TokenType::Blank => Operand::Name(self.parse_ident()?),
+            TokenType::OpenParen => {
+                self.advance();
+                self.expr_lev += 1;
+                let expr = self.parse_expr();
+                self.expr_lev -= 1;
+                let expr = expr?;
+                self.expect(TokenType::ClosedParen)?;
+                Operand::Expr(expr.item)
+            }
+            _ => match Literal::from_token(self.token) {
+                Some(lit) => {
+                    self.advance();
+                    Operand::Lit(lit)
                 }
+                None => return Err(self.unexpected("expression")),
+            },
+        };
+        Ok(Spanned::new(self.span_from(beg), operand))
+    }
+
+    // Type = TypeName | TypeLit | "(" Type ")" .
+    fn parse_type(&mut self) -> Result<Type, ParseError> {
+        match self.token.kind {
+            TokenType::Ident => {
+                let name = self.parse_type_name()?;
+                self.parse_type_args_of(name)
+            }
+            TokenType::Star => {
+                self.advance();
+                Ok(Type::Pointer(Box::new(self.parse_type()?)))
+            }
+            TokenType::OpenSquare => {
+                self.advance();
+                self.parse_array_or_slice()
+            }
+            TokenType::Map => {
+                self.advance();
+                self.expect(TokenType::OpenSquare)?;
+                let key = Box::new(self.parse_type()?);
+                self.expect(TokenType::ClosedSquare)?;
+                let value = Box::new(self.parse_type()?);
+                Ok(Type::Map(MapType { key, value }))
+            }
+            // chan T, chan<- T or <-chan T
+            TokenType::Chan => {
+                self.advance();
+                let dir = if self.eat(TokenType::Arrow) {
+                    ChanDir::Send
+                } else {
+                    ChanDir::Both
+                };
+                let elem = Box::new(self.parse_type()?);
+                Ok(Type::Chan(ChanType { dir, elem }))
+            }
+            TokenType::Arrow => {
+                self.advance();
+                self.expect(TokenType::Chan)?;
+                let elem = Box::new(self.parse_type()?);
+                Ok(Type::Chan(ChanType {
+                    dir: ChanDir::Recv,
+                    elem,
+                }))
             }
+            TokenType::Func => {
+                self.advance();
+                Ok(Type::Func(self.parse_signature()?))
+            }
+            TokenType::Struct => Ok(Type::Struct(self.parse_struct_type()?)),
+            TokenType::Interface => Ok(Type::Interface(self.parse_interface_type()?)),
+            TokenType::OpenParen => {
+                self.advance();
+                let typ = self.parse_type()?;
+                self.expect(TokenType::ClosedParen)?;
+                Ok(typ)
+            }
+            _ => Err(self.unexpected("type")),
+        }
+    }
+    // The rest of []T or [N]T, after the `[`
+    fn parse_array_or_slice(&mut self) -> Result<Type, ParseError> {
+        if self.eat(TokenType::ClosedSquare) {
+            return Ok(Type::Slice(Box::new(self.parse_type()?)));
+        }
+        // [...]T, the length is however many elements the composite literal has
+        let len = if self.token.kind == TokenType::Ellipsis
+            && self.peek_kind() == TokenType::ClosedSquare
+        {
+            self.advance();
+            None
+        } else {
+            self.expr_lev += 1;
+            let len = self.parse_expr();
+            self.expr_lev -= 1;
+            Some(Box::new(len?))
+        };
+        self.expect(TokenType::ClosedSquare)?;
+        let elem = Box::new(self.parse_type()?);
+        Ok(Type::Array(ArrayType { len, elem }))
+    }
+    // TypeArgs = "[" TypeList [ "," ] "]" .
+    // A type name followed by type arguments is an instance of a generic type.
+    fn parse_type_args_of(&mut self, name: TypeName) -> Result<Type, ParseError> {
+        if !self.eat(TokenType::OpenSquare) {
+            return Ok(Type::Name(name));
+        }
+        let mut args = vec![self.parse_type()?];
+        while self.eat(TokenType::Comma) && self.token.kind != TokenType::ClosedSquare {
+            args.push(self.parse_type()?);
+        }
+        self.expect(TokenType::ClosedSquare)?;
+        Ok(Type::Instance(TypeInstance { name, args }))
+    }
+    // `x [` in a parameter list or struct is either a name followed by an array
+    // or slice type, like a [4]int or s []int, or a generic type, List[int]. We
+    // can only tell after the `]`: an array still has its element type to come.
+    fn parse_name_or_instance(
+        &mut self,
+        ident: Ident,
+    ) -> Result<(Option<Ident>, Type), ParseError> {
+        self.expect(TokenType::OpenSquare)?;
+        if self.token.kind == TokenType::ClosedSquare {
+            return Ok((Some(ident), self.parse_array_or_slice()?));
+        }
+        self.expr_lev += 1;
+        let args = self.parse_type_or_expr_list();
+        self.expr_lev -= 1;
+        let mut args = args?;
+        self.expect(TokenType::ClosedSquare)?;
+        if starts_type(self.token.kind) && matches!(args[..], [TypeOrExpr::Expr(_)]) {
+            let Some(TypeOrExpr::Expr(len)) = args.pop() else {
+                unreachable!()
+            };
+            let elem = Box::new(self.parse_type()?);
+            let len = Some(Box::new(len));
+            return Ok((Some(ident), Type::Array(ArrayType { len, elem })));
+        }
+        let args = args
+            .into_iter()
+            .map(TypeOrExpr::into_type)
+            .collect::<Result<_, _>>()?;
+        let name = TypeName {
+            package: None,
+            name: ident,
+        };
+        Ok((None, Type::Instance(TypeInstance { name, args })))
+    }
+    // TypeElem = TypeTerm { "|" TypeTerm } .
+    // TypeTerm = Type | "~" Type .
+    fn parse_type_elem(&mut self) -> Result<Vec<TypeTerm>, ParseError> {
+        let mut terms = Vec::new();
+        loop {
+            // the lexer has no token of its own for ~
+            let tilde = self.token.kind == TokenType::Operand && self.token.value.as_str() == "~";
+            if tilde {
+                self.advance();
+            }
+            let typ = self.parse_type()?;
+            terms.push(TypeTerm { tilde, typ });
+            if !self.eat(TokenType::Or) {
+                return Ok(terms);
+            }
+        }
+    }
+    // StructType = "struct" "{" { FieldDecl ";" } "}" .
+    // FieldDecl  = (IdentifierList Type | EmbeddedField) [ Tag ] .
+    fn parse_struct_type(&mut self) -> Result<StructType, ParseError> {
+        self.expect(TokenType::Struct)?;
+        self.expect(TokenType::OpenBracket)?;
+        let mut fields = Vec::new();
+        while self.token.kind != TokenType::ClosedBracket {
+            let mut names = Vec::new();
+            let typ = match self.token.kind {
+                // embedded *T
+                TokenType::Star => self.parse_type()?,
+                TokenType::Ident => {
+                    let ident = self.parse_ident()?;
+                    match self.token.kind {
+                        // embedded T or pkg.T
+                        TokenType::Semicolon
+                        | TokenType::ClosedBracket
+                        | TokenType::StringLiteral => Type::Name(TypeName {
+                            package: None,
+                            name: ident,
+                        }),
+                        TokenType::Period => {
+                            self.advance();
+                            let name = TypeName {
+                                package: Some(ident),
+                                name: self.parse_ident()?,
+                            };
+                            self.parse_type_args_of(name)?
+                        }
+                        TokenType::OpenSquare => {
+                            let (name, typ) = self.parse_name_or_instance(ident)?;
+                            names.extend(name);
+                            typ
+                        }
+                        _ => {
+                            names.push(ident);
+                            while self.eat(TokenType::Comma) {
+                                names.push(self.parse_ident()?);
+                            }
+                            self.parse_type()?
+                        }
+                    }
+                }
+                _ => return Err(self.unexpected("field name or embedded type")),
+            };
+            let tag = match self.token.kind {
+                TokenType::StringLiteral => Some(self.advance().value),
+                _ => None,
+            };
+            fields.push(FieldDecl { names, typ, tag });
+            if self.token.kind != TokenType::ClosedBracket {
+                self.expect(TokenType::Semicolon)?;
+            }
+        }
+        self.expect(TokenType::ClosedBracket)?;
+        Ok(StructType { fields })
+    }
+    // InterfaceType = "interface" "{" { InterfaceElem ";" } "}" .
+    // InterfaceElem = MethodElem | TypeElem .
+    fn parse_interface_type(&mut self) -> Result<InterfaceType, ParseError> {
+        self.expect(TokenType::Interface)?;
+        self.expect(TokenType::OpenBracket)?;
+        let mut elems = Vec::new();
+        while self.token.kind != TokenType::ClosedBracket {
+            if self.token.kind == TokenType::Ident && self.peek_kind() == TokenType::OpenParen {
+                let name = self.parse_ident()?;
+                let signature = self.parse_signature()?;
+                elems.push(InterfaceElem::Method(MethodSpec { name, signature }));
+            } else {
+                let mut terms = self.parse_type_elem()?;
+                if terms.len() == 1 && !terms[0].tilde {
+                    elems.push(InterfaceElem::Embedded(terms.pop().unwrap().typ));
+                } else {
+                    elems.push(InterfaceElem::Union(terms));
+                }
+            }
+            if self.token.kind != TokenType::ClosedBracket {
+                self.expect(TokenType::Semicolon)?;
+            }
+        }
+        self.expect(TokenType::ClosedBracket)?;
+        Ok(InterfaceType { elems })
+    }
+    // TypeName = identifier | PackageName "." identifier .
+    fn parse_type_name(&mut self) -> Result<TypeName, ParseError> {
+        let name = self.parse_ident()?;
+        if self.eat(TokenType::Period) {
+            return Ok(TypeName {
+                package: Some(name),
+                name: self.parse_ident()?,
+            });
+        }
+        Ok(TypeName {
+            package: None,
+            name,
+        })
+    }
+}
+
+// The names on the left of a := have to be plain identifiers
+fn idents_of(exprs: &[Spanned<Expr>]) -> Result<Vec<Ident>, ParseError> {
+    let mut names = Vec::new();
+    for expr in exprs {
+        match expr_ident(expr) {
+            Some(name) => names.push(name),
+            None => {
+                return Err(ParseError::invalid(
+                    "non-name on left side of :=",
+                    expr.span,
+                ))
+            }
+        }
+    }
+    Ok(names)
+}
+
+// The identifier an expression consists of, if that is all it is
+fn expr_ident(expr: &Spanned<Expr>) -> Option<Ident> {
+    match &expr.item {
+        Expr::Unary(UnaryExpr::Primary(primary)) => match **primary {
+            PrimaryExpr::Operand(Operand::Name(name)) => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// TypeSwitchGuard = [ identifier ":=" ] PrimaryExpr "." "(" "type" ")" .
+// Hands back the identifier and the expression being switched on.
+fn type_switch_guard(stmt: &SimpleStmt) -> Option<(Option<Ident>, Box<Spanned<PrimaryExpr>>)> {
+    let (binding, expr) = match stmt {
+        SimpleStmt::Expr(expr) => (None, expr),
+        SimpleStmt::ShortVarDecl(decl) if decl.names.len() == 1 && decl.values.len() == 1 => {
+            (Some(decl.names[0]), &decl.values[0])
+        }
+        _ => return None,
+    };
+    match &expr.item {
+        Expr::Unary(UnaryExpr::Primary(primary)) => match &**primary {
+            PrimaryExpr::TypeAssertion(TypeAssertion { expr, typ: None }) => {
+                Some((binding, expr.clone()))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Types that can't be mistaken for the start of an ordinary expression
+fn starts_type_literal(kind: TokenType) -> bool {
+    matches!(
+        kind,
+        TokenType::OpenSquare
+            | TokenType::Map
+            | TokenType::Chan
+            | TokenType::Struct
+            | TokenType::Interface
+    )
+}
+
+fn starts_type(kind: TokenType) -> bool {
+    matches!(
+        kind,
+        TokenType::Ident
+            | TokenType::Star
+            | TokenType::OpenSquare
+            | TokenType::OpenParen
+            | TokenType::Map
+            | TokenType::Chan
+            | TokenType::Arrow
+            | TokenType::Func
+            | TokenType::Struct
+            | TokenType::Interface
+    )
+}
+
+// Whether a token can come after the first name in type parameters, where it
+// starts the constraint or the next name
+fn starts_constraint(kind: TokenType) -> bool {
+    matches!(
+        kind,
+        TokenType::Ident
+            | TokenType::Comma
+            | TokenType::Operand
+            | TokenType::OpenSquare
+            | TokenType::Map
+            | TokenType::Chan
+            | TokenType::Func
+            | TokenType::Struct
+            | TokenType::Interface
+    )
+}
+
+// Either of them can go in a type argument list
+enum TypeOrExpr {
+    Type(Type),
+    Expr(Spanned<Expr>),
+}
+
+impl TypeOrExpr {
+    fn into_type(self) -> Result<Type, ParseError> {
+        match self {
+            TypeOrExpr::Type(typ) => Ok(typ),
+            TypeOrExpr::Expr(expr) => match type_of_expr(&expr.item) {
+                Some(typ) => Ok(typ),
+                None => Err(ParseError::invalid("expected type argument", expr.span)),
+            },
+        }
+    }
+}
+
+// An expression that could be a type, which we can't know until we see where
+// it is used: T, *T, pkg.T or List[T]
+fn type_of_expr(expr: &Expr) -> Option<Type> {
+    match expr {
+        Expr::Unary(unary) => type_of_unary(unary),
+        Expr::Binary(_) => None,
+    }
+}
+fn type_of_unary(unary: &UnaryExpr) -> Option<Type> {
+    match unary {
+        UnaryExpr::Primary(primary) => match &**primary {
+            PrimaryExpr::Operand(Operand::Expr(expr)) => type_of_expr(expr),
+            primary => type_of_primary(primary),
+        },
+        UnaryExpr::UnaryOperation(UnaryOperation {
+            operator: UnaryOperator::Deref,
+            operand,
+        }) => Some(Type::Pointer(Box::new(type_of_unary(&operand.item)?))),
+        UnaryExpr::UnaryOperation(_) => None,
+    }
+}
+
+// A primary expression that could be naming a type, with or without type
+// arguments. These are the ones that can start a composite literal.
+fn type_of_primary(primary: &PrimaryExpr) -> Option<Type> {
+    match primary {
+        PrimaryExpr::IndexOrInstance(IndexOrInstance { operand, typ, .. }) => {
+            Some(Type::Instance(TypeInstance {
+                name: type_name_of(&operand.item)?,
+                args: vec![typ.clone()],
+            }))
+        }
+        PrimaryExpr::Instantiation(Instantiation { operand, args }) => {
+            Some(Type::Instance(TypeInstance {
+                name: type_name_of(&operand.item)?,
+                args: args.clone(),
+            }))
+        }
+        _ => type_name_of(primary).map(Type::Name),
+    }
+}
+
+// A primary expression that could be naming a type, T or pkg.T
+fn type_name_of(primary: &PrimaryExpr) -> Option<TypeName> {
+    match primary {
+        PrimaryExpr::Operand(Operand::Name(name)) => Some(TypeName {
+            package: None,
+            name: *name,
+        }),
+        PrimaryExpr::SelectorExpr(SelectorExpr { operand, selector }) => match operand.item {
+            PrimaryExpr::Operand(Operand::Name(package)) => Some(TypeName {
+                package: Some(package),
+                name: *selector,
+            }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// A select case is parsed as a simple statement, this is the part of them
+// that can be one: a send, or a receive that is on its own or assigned
+fn comm_case_of(stmt: SimpleStmt) -> Option<CommCase> {
+    let (vars, mut values) = match stmt {
+        SimpleStmt::Send(send) => return Some(CommCase::Send(send)),
+        SimpleStmt::Expr(expr) => (None, vec![expr]),
+        SimpleStmt::ShortVarDecl(ShortVarDecl { names, values }) => {
+            (Some(IterVars::Idents(names)), values)
+        }
+        SimpleStmt::Assignment(Assignment { lhs, rhs, op: None }) => {
+            (Some(IterVars::Exprs(lhs)), rhs)
+        }
+        _ => return None,
+    };
+    match values.pop() {
+        Some(recv) if values.is_empty() && is_recv(&recv.item) => {
+            Some(CommCase::Recv(RecvStmt { vars, recv }))
+        }
+        _ => None,
+    }
+}
+
+fn is_recv(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Unary(UnaryExpr::UnaryOperation(UnaryOperation {
+            operator: UnaryOperator::Recv,
+            ..
+        }))
+    )
+}
+
+fn is_call(expr: &Expr) -> bool {
+    match expr {
+        Expr::Unary(UnaryExpr::Primary(primary)) => {
+            matches!(**primary, PrimaryExpr::FuncCall(_))
         }
+        _ => false,
     }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Expr {
@@ -169,49 +2380,49 @@ pub enum BinaryOperator {
 }
 
 impl BinaryOperator {
-    pub fn from_token_kind(tok: TokenKind) -> Option<BinaryOperator> {
+    pub fn from_token_kind(tok: TokenType) -> Option<BinaryOperator> {
         use self::BinaryOperator::*;
         Some(match tok {
-            TokenKind::Plus => Add,
-            TokenKind::Minus => Sub,
-            TokenKind::Star => Mul,
-            TokenKind::FwdSlash => Div,
-            TokenKind::Percent => Rem,
-            TokenKind::And => BitAnd,
-            TokenKind::Or => BitOr,
-            TokenKind::Caret => BitXor,
-            TokenKind::BitClear => BitClear,
-            TokenKind::Lshift => LeftShift,
-            TokenKind::Rshift => RightShift,
-            TokenKind::Equals => Equals,
-            TokenKind::NotEqual => NotEqual,
-            TokenKind::LessThan => LessThan,
-            TokenKind::LeftCaret => LessThanOrEqual,
-            TokenKind::GreaterThan => GreaterThan,
-            TokenKind::GreaterThanOrEqual => GreaterThanOrEqual,
-            TokenKind::AndAnd => LogAnd,
-            TokenKind::OrOr => LogOr,
+            TokenType::Plus => Add,
+            TokenType::Minus => Sub,
+            TokenType::Star => Mul,
+            TokenType::FwdSlash => Div,
+            TokenType::Modulo => Rem,
+            TokenType::And => BitAnd,
+            TokenType::Or => BitOr,
+            TokenType::Caret => BitXor,
+            TokenType::BitClear => BitClear,
+            TokenType::Lshift => LeftShift,
+            TokenType::Rshift => RightShift,
+            TokenType::Equals => Equals,
+            TokenType::NotEqual => NotEqual,
+            TokenType::LeftCaret => LessThan,
+            TokenType::LessThanOrEqual => LessThanOrEqual,
+            TokenType::RightCaret => GreaterThan,
+            TokenType::GreaterThanOrEqual => GreaterThanOrEqual,
+            TokenType::AndAnd => LogAnd,
+            TokenType::OrOr => LogOr,
 
             _ => return None,
         })
     }
 
-    pub fn from_token_kind_assign_op(tok: TokenKind) -> Option<BinaryOperator> {
+    pub fn from_token_kind_assign_op(tok: TokenType) -> Option<BinaryOperator> {
         use self::BinaryOperator::*;
         Some(match tok {
-            TokenKind::PlusAssign => Add,
-            TokenKind::MinusAssign => Sub,
-            TokenKind::StarAssign => Mul,
-            TokenKind::SlashAssign => Div,
-            TokenKind::PercentAssign => Rem,
+            TokenType::PlusAssign => Add,
+            TokenType::MinusAssign => Sub,
+            TokenType::StarAssign => Mul,
+            TokenType::SlashAssign => Div,
+            TokenType::ModuloAssign => Rem,
 
-            TokenKind::AndAssign => BitAnd,
-            TokenKind::OrAssign => BitOr,
-            TokenKind::CaretAssign => BitXor,
-            TokenKind::BitClearAssign => BitClear,
+            TokenType::AndAssign => BitAnd,
+            TokenType::OrAssign => BitOr,
+            TokenType::CaretAssign => BitXor,
+            TokenType::BitClearAssign => BitClear,
 
-            TokenKind::LshiftAssign => LeftShift,
-            TokenKind::RshiftAssign => RightShift,
+            TokenType::LshiftAssign => LeftShift,
+            TokenType::RshiftAssign => RightShift,
 
             _ => return None,
         })
@@ -228,6 +2439,7 @@ impl BinaryOperator {
             LogOr => 1,
         }
     }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BinaryExpr {
@@ -255,19 +2467,47 @@ pub enum UnaryOperator {
     Xor,
     Deref,
     And,
+    /// `<-ch`, receiving from a channel
+    Recv,
 }
 
 impl UnaryOperator {
-    pub fn from_token_kind(k: TokenKind) -> Option<UnaryOperator> {
+    pub fn from_token_kind(k: TokenType) -> Option<UnaryOperator> {
         use self::UnaryOperator::*;
 
         Some(match k {
-            TokenKind::Plus => Plus,
-            TokenKind::Minus => Minus,
-            TokenKind::Not => Not,
-            TokenKind::Caret => Xor,
-            TokenKind::Star => Deref,
-            TokenKind::And => And,
+            TokenType::Plus => Plus,
+            TokenType::Minus => Minus,
+            TokenType::Bang => Not,
+            TokenType::Caret => Xor,
+            TokenType::Star => Deref,
+            TokenType::And => And,
+            TokenType::Arrow => Recv,
+            _ => return None,
+        })
+    }
+}
+
+/// A basic literal. The value is whatever the lexer produced: numbers as they were
+/// written, strings and runes already decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Literal {
+    Int(Symbol),
+    Float(Symbol),
+    Imag(Symbol),
+    Rune(Symbol),
+    String(Symbol),
+}
+
+impl Literal {
+    pub fn from_token(tok: Token) -> Option<Literal> {
+        use self::Literal::*;
+        Some(match tok.kind {
+            TokenType::IntLiteral => Int(tok.value),
+            TokenType::FloatLiteral => Float(tok.value),
+            TokenType::ImagLiteral => Imag(tok.value),
+            TokenType::RuneLiteral => Rune(tok.value),
+            TokenType::StringLiteral => String(tok.value),
             _ => return None,
         })
     }
@@ -279,6 +2519,8 @@ pub enum PrimaryExpr {
     Conversion(Conversion),
     SelectorExpr(SelectorExpr),
     Indexing(IndexExpr),
+    IndexOrInstance(IndexOrInstance),
+    Instantiation(Instantiation),
     Slicing(SliceExpr),
     TypeAssertion(TypeAssertion),
     FuncCall(FuncCall),
@@ -287,17 +2529,55 @@ pub enum PrimaryExpr {
 /// Operands denote the elementary values in an expression. An operand may be a literal, a
 /// (possibly qualified) non-blank identifier denoting a constant, variable, or function, a method
 /// expression yielding a function, or a parenthesized expression.
-// XXX/FIXME/TODO: not finished.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Operand {
     Lit(Literal),
+    Name(Ident),
+    CompositeLit(CompositeLit),
+    FuncLit(FuncLit),
     MethodExpr(MethodExpr),
     Expr(Expr),
 }
 
+/// T{...}, T is any type that can be constructed this way: arrays, slices,
+/// maps, structs and names of those.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompositeLit {
+    pub typ: Type,
+    pub value: Spanned<LiteralValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiteralValue {
+    pub elems: Vec<KeyedElement>,
+}
+
+/// The key is a field name for structs, an index for arrays and slices and
+/// any key value for maps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyedElement {
+    pub key: Option<Element>,
+    pub value: Element,
+}
+
+/// An element that is itself a composite literal can leave out its type, like
+/// the inner braces of [][]int{{1, 2}, {3}}.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Element {
+    Expr(Spanned<Expr>),
+    Lit(Spanned<LiteralValue>),
+}
+
+/// A function literal is a closure, it can refer to variables of the function it is in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuncLit {
+    pub signature: Signature,
+    pub body: Block,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SelectorExpr {
-    pub operand: Box<PrimaryExpr>,
+    pub operand: Box<Spanned<PrimaryExpr>>,
     pub selector: Ident,
 }
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -314,13 +2594,33 @@ pub struct SliceExpr {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Slicing {
-    pub low: Spanned<Expr>,
-    pub high: Spanned<Expr>,
+    pub low: Option<Spanned<Expr>>,
+    pub high: Option<Spanned<Expr>>,
     pub max: Option<Spanned<Expr>>,
 }
+/// F[int] or a[i]: a name indexed by something that could also be a type.
+/// Whether it indexes or instantiates a generic function or type depends on
+/// what the name refers to, so both readings are kept for later to pick from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexOrInstance {
+    pub operand: Box<Spanned<PrimaryExpr>>,
+    pub index: Spanned<Expr>,
+    /// `index` read as a type argument
+    pub typ: Type,
+}
+
+/// F[int, string] or F[[]int], a generic function or type given its type
+/// arguments where they can't be mistaken for an index. F[int] on its own is
+/// an IndexOrInstance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instantiation {
+    pub operand: Box<Spanned<PrimaryExpr>>,
+    pub args: Vec<Type>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TypeAssertion {
-    pub expr: Box<PrimaryExpr>,
+    pub expr: Box<Spanned<PrimaryExpr>>,
     pub typ: Option<Type>,
 }
 
@@ -330,10 +2630,1194 @@ pub struct FuncCall {
     pub args: Arguments,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arguments {
+    /// Leading type argument of builtins like make and new.
+    pub typ: Option<Type>,
+    pub exprs: Vec<Spanned<Expr>>,
+    /// The last argument was followed by `...`.
+    pub spread: bool,
+}
+
+/// Conversion of an expression to a type literal, like []byte(s).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conversion {
+    pub typ: Type,
+    pub expr: Box<Spanned<Expr>>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MethodExpr {
     /// Receiver type.
     pub receiver: Type,
     /// Name of the method.
-    pub name: String,
+    pub name: Symbol,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Name(TypeName),
+    Instance(TypeInstance),
+    Array(ArrayType),
+    Slice(Box<Type>),
+    Map(MapType),
+    Chan(ChanType),
+    Pointer(Box<Type>),
+    Func(Signature),
+    Struct(StructType),
+    Interface(InterfaceType),
+}
+
+/// A type name, qualified with the package it comes from if it is imported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeName {
+    pub package: Option<Ident>,
+    pub name: Ident,
+}
+
+/// A generic type with its type arguments, List[int] or pkg.Pair[K, V].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeInstance {
+    pub name: TypeName,
+    pub args: Vec<Type>,
+}
+
+/// `len` is None for [...]T, which is only allowed in a composite literal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArrayType {
+    pub len: Option<Box<Spanned<Expr>>>,
+    pub elem: Box<Type>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapType {
+    pub key: Box<Type>,
+    pub value: Box<Type>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChanType {
+    pub dir: ChanDir,
+    pub elem: Box<Type>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChanDir {
+    Both,
+    /// chan<- T
+    Send,
+    /// <-chan T
+    Recv,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructType {
+    pub fields: Vec<FieldDecl>,
+}
+
+/// An embedded field has no names, its type doubles as the field name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDecl {
+    pub names: Vec<Ident>,
+    pub typ: Type,
+    pub tag: Option<Symbol>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceType {
+    pub elems: Vec<InterfaceElem>,
+}
+
+/// A single type without a tilde is Embedded, anything else like `~int | ~float64`
+/// is a Union.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterfaceElem {
+    Method(MethodSpec),
+    Embedded(Type),
+    Union(Vec<TypeTerm>),
+}
+
+/// One alternative of a type union, with the tilde it stands for every type
+/// whose underlying type is `typ`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeTerm {
+    pub tilde: bool,
+    pub typ: Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodSpec {
+    pub name: Ident,
+    pub signature: Signature,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn parse(src: &str) -> (SourceFile, Vec<String>) {
+        let mut parser = Parser::new(Lexer::new(src.to_string()));
+        let file = parser.parse();
+        let errors = parser.errors.iter().map(|e| e.kind.to_string()).collect();
+        (file, errors)
+    }
+    fn parse_ok(src: &str) -> SourceFile {
+        let (file, errors) = parse(src);
+        assert_eq!(errors, Vec::<String>::new(), "{}", src);
+        file
+    }
+    fn decls(src: &str) -> Vec<TopLevelDecl> {
+        let file = parse_ok(&format!("package p\n{}\n", src));
+        file.decls.into_iter().map(|d| d.item).collect()
+    }
+    // the statements of `src` as the body of a function
+    fn stmts(src: &str) -> Vec<Statement> {
+        match decls(&format!("func f() {{\n{}\n}}", src)).remove(0) {
+            TopLevelDecl::Func(FuncDecl {
+                body: Some(body), ..
+            }) => body.stmts.into_iter().map(|s| s.item).collect(),
+            decl => panic!("{:?}", decl),
+        }
+    }
+    fn stmt(src: &str) -> Statement {
+        let mut stmts = stmts(src);
+        assert_eq!(stmts.len(), 1, "{}: {:?}", src, stmts);
+        stmts.remove(0)
+    }
+
+    fn stmt_kind(stmt: &Statement) -> &'static str {
+        match stmt {
+            Statement::Decl(DeclStmt::Const(_)) => "const",
+            Statement::Decl(DeclStmt::VarDecl(_)) => "var",
+            Statement::Decl(DeclStmt::TypeDecl(_)) => "type",
+            Statement::Labeled(_) => "labeled",
+            Statement::Simple(SimpleStmt::EmptyStmt) => "empty",
+            Statement::Simple(SimpleStmt::Expr(_)) => "expr",
+            Statement::Simple(SimpleStmt::Send(_)) => "send",
+            Statement::Simple(SimpleStmt::IncDec(_)) => "incdec",
+            Statement::Simple(SimpleStmt::Assignment(_)) => "assign",
+            Statement::Simple(SimpleStmt::ShortVarDecl(_)) => "define",
+            Statement::Return(_) => "return",
+            Statement::Break(_) => "break",
+            Statement::Continue(_) => "continue",
+            Statement::Goto(_) => "goto",
+            Statement::Fallthrough(_) => "fallthrough",
+            Statement::Block(_) => "block",
+            Statement::If(_) => "if",
+            Statement::Switch(_) => "switch",
+            Statement::TypeSwitch(_) => "typeswitch",
+            Statement::Select(_) => "select",
+            Statement::For(_) => "for",
+            Statement::Go(_) => "go",
+            Statement::Defer(_) => "defer",
+            Statement::Empty(_) => "empty",
+            Statement::Error => "error",
+        }
+    }
+
+    fn expr(src: &str) -> Expr {
+        match stmt(&format!("_ = {}", src)) {
+            Statement::Simple(SimpleStmt::Assignment(mut a)) => a.rhs.remove(0).item,
+            stmt => panic!("{:?}", stmt),
+        }
+    }
+    // A compact rendering of an expression that makes its shape plain,
+    // `a + b * c` comes out as (a + (b * c))
+    fn show(expr: &Expr) -> String {
+        match expr {
+            Expr::Binary(b) => format!(
+                "({} {} {})",
+                show(&b.lhs.item),
+                show_op(b.op),
+                show(&b.rhs.item)
+            ),
+            Expr::Unary(u) => show_unary(u),
+        }
+    }
+    fn show_unary(u: &UnaryExpr) -> String {
+        match u {
+            UnaryExpr::Primary(p) => show_primary(p),
+            UnaryExpr::UnaryOperation(op) => {
+                let sym = match op.operator {
+                    UnaryOperator::Plus => "+",
+                    UnaryOperator::Minus => "-",
+                    UnaryOperator::Not => "!",
+                    UnaryOperator::Xor => "^",
+                    UnaryOperator::Deref => "*",
+                    UnaryOperator::And => "&",
+                    UnaryOperator::Recv => "<-",
+                };
+                format!("{}{}", sym, show_unary(&op.operand.item))
+            }
+        }
+    }
+    fn show_primary(p: &PrimaryExpr) -> String {
+        let list = |exprs: &[Spanned<Expr>]| {
+            exprs
+                .iter()
+                .map(|e| show(&e.item))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let opt = |e: &Option<Spanned<Expr>>| e.as_ref().map(|e| show(&e.item)).unwrap_or_default();
+        match p {
+            PrimaryExpr::Operand(Operand::Name(name)) => name.item.to_string(),
+            PrimaryExpr::Operand(Operand::Lit(lit)) => match lit {
+                Literal::String(s) => format!("{:?}", s.as_str()),
+                Literal::Int(s) | Literal::Float(s) | Literal::Imag(s) | Literal::Rune(s) => {
+                    s.to_string()
+                }
+            },
+            PrimaryExpr::Operand(Operand::Expr(e)) => show(e),
+            PrimaryExpr::Operand(Operand::CompositeLit(lit)) => {
+                format!("{}{{{}}}", show_type(&lit.typ), lit.value.item.elems.len())
+            }
+            PrimaryExpr::Operand(Operand::FuncLit(_)) => "func".to_string(),
+            PrimaryExpr::Operand(Operand::MethodExpr(m)) => {
+                format!("({}).{}", show_type(&m.receiver), m.name)
+            }
+            PrimaryExpr::Conversion(c) => format!("{}({})", show_type(&c.typ), show(&c.expr.item)),
+            PrimaryExpr::SelectorExpr(s) => {
+                format!("{}.{}", show_primary(&s.operand.item), s.selector.item)
+            }
+            PrimaryExpr::Indexing(i) => {
+                format!("{}[{}]", show_primary(&i.operand.item), show(&i.index.item))
+            }
+            PrimaryExpr::IndexOrInstance(i) => {
+                format!("{}[{}]?", show_primary(&i.operand.item), show_type(&i.typ))
+            }
+            PrimaryExpr::Instantiation(i) => format!(
+                "{}[{}]",
+                show_primary(&i.operand.item),
+                i.args.iter().map(show_type).collect::<Vec<_>>().join(", ")
+            ),
+            PrimaryExpr::Slicing(s) => {
+                let mut out = format!(
+                    "{}[{}:{}",
+                    show_primary(&s.operand.item),
+                    opt(&s.slicing.low),
+                    opt(&s.slicing.high)
+                );
+                if let Some(max) = &s.slicing.max {
+                    out += &format!(":{}", show(&max.item));
+                }
+                out + "]"
+            }
+            PrimaryExpr::TypeAssertion(t) => format!(
+                "{}.({})",
+                show_primary(&t.expr.item),
+                t.typ.as_ref().map(show_type).unwrap_or("type".to_string())
+            ),
+            PrimaryExpr::FuncCall(call) => {
+                let mut args: Vec<String> = call.args.typ.iter().map(show_type).collect();
+                if !call.args.exprs.is_empty() {
+                    args.push(list(&call.args.exprs));
+                }
+                let spread = if call.args.spread { "..." } else { "" };
+                format!(
+                    "{}({}{})",
+                    show_primary(&call.callee.item),
+                    args.join(", "),
+                    spread
+                )
+            }
+        }
+    }
+    fn show_type(t: &Type) -> String {
+        let name = |n: &TypeName| match n.package {
+            Some(pkg) => format!("{}.{}", pkg.item, n.name.item),
+            None => n.name.item.to_string(),
+        };
+        match t {
+            Type::Name(n) => name(n),
+            Type::Instance(i) => format!(
+                "{}[{}]",
+                name(&i.name),
+                i.args.iter().map(show_type).collect::<Vec<_>>().join(", ")
+            ),
+            Type::Array(a) => match &a.len {
+                Some(len) => format!("[{}]{}", show(&len.item), show_type(&a.elem)),
+                None => format!("[...]{}", show_type(&a.elem)),
+            },
+            Type::Slice(elem) => format!("[]{}", show_type(elem)),
+            Type::Map(m) => format!("map[{}]{}", show_type(&m.key), show_type(&m.value)),
+            Type::Chan(c) => match c.dir {
+                ChanDir::Both => format!("chan {}", show_type(&c.elem)),
+                ChanDir::Send => format!("chan<- {}", show_type(&c.elem)),
+                ChanDir::Recv => format!("<-chan {}", show_type(&c.elem)),
+            },
+            Type::Pointer(elem) => format!("*{}", show_type(elem)),
+            Type::Func(sig) => format!("func/{}/{}", sig.params.len(), sig.results.len()),
+            Type::Struct(s) => format!("struct/{}", s.fields.len()),
+            Type::Interface(i) => format!("interface/{}", i.elems.len()),
+        }
+    }
+    fn show_op(op: BinaryOperator) -> &'static str {
+        use BinaryOperator::*;
+        match op {
+            Add => "+",
+            Sub => "-",
+            Mul => "*",
+            Div => "/",
+            Rem => "%",
+            BitAnd => "&",
+            BitOr => "|",
+            BitXor => "^",
+            BitClear => "&^",
+            LeftShift => "<<",
+            RightShift => ">>",
+            Equals => "==",
+            NotEqual => "!=",
+            LessThan => "<",
+            LessThanOrEqual => "<=",
+            GreaterThan => ">",
+            GreaterThanOrEqual => ">=",
+            LogAnd => "&&",
+            LogOr => "||",
+        }
+    }
+
+    fn typ(src: &str) -> Type {
+        match decls(&format!("var v {}", src)).remove(0) {
+            TopLevelDecl::Decl(DeclStmt::VarDecl(mut v)) => v.specs.remove(0).typ.unwrap(),
+            decl => panic!("{:?}", decl),
+        }
+    }
+
+    fn primary(src: &str) -> PrimaryExpr {
+        match expr(src) {
+            Expr::Unary(UnaryExpr::Primary(p)) => *p,
+            e => panic!("{}: {:?}", src, e),
+        }
+    }
+
+    #[test]
+    fn statements() {
+        let src = "
+            x := 1
+            x = 2
+            x, y = y, x
+            x += 3
+            x++
+            ch <- x
+            f()
+            L: for {}
+            goto L
+            if x > 0 {}
+            switch x {}
+            switch y.(type) {}
+            select {}
+            go f()
+            defer g()
+            var v int
+            const c = 1
+            type T int
+            {}
+            for { break; continue }
+            return 1, 2
+        ";
+        let kinds: Vec<&str> = stmts(src).iter().map(stmt_kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "define",
+                "assign",
+                "assign",
+                "assign",
+                "incdec",
+                "send",
+                "expr",
+                "labeled",
+                "goto",
+                "if",
+                "switch",
+                "typeswitch",
+                "select",
+                "go",
+                "defer",
+                "var",
+                "const",
+                "type",
+                "block",
+                "for",
+                "return",
+            ]
+        );
+        match stmt("x <<= 2") {
+            Statement::Simple(SimpleStmt::Assignment(a)) => {
+                assert_eq!(a.op, Some(BinaryOperator::LeftShift))
+            }
+            s => panic!("{:?}", s),
+        }
+        match stmt("outer: for { continue outer }") {
+            Statement::Labeled(l) => {
+                assert_eq!(l.label.item.as_str(), "outer");
+                assert!(matches!(l.stmt.item, Statement::For(_)));
+            }
+            s => panic!("{:?}", s),
+        }
+        let (_, errors) = parse("package p\nfunc f() { a.b := 1 }\n");
+        assert_eq!(errors, vec!["non-name on left side of :="]);
+    }
+
+    #[test]
+    fn precedence() {
+        let table = [
+            ("a + b * c", "(a + (b * c))"),
+            ("a * b + c", "((a * b) + c)"),
+            ("a - b - c", "((a - b) - c)"),
+            ("a / b * c", "((a / b) * c)"),
+            ("a || b && c == d", "(a || (b && (c == d)))"),
+            ("a == b || c < d && e", "((a == b) || ((c < d) && e))"),
+            ("a << 1 | b &^ c", "((a << 1) | (b &^ c))"),
+            ("a + b < c ^ d", "((a + b) < (c ^ d))"),
+            ("-a * b", "(-a * b)"),
+            ("*p + 1", "(*p + 1)"),
+            ("<-ch + 1", "(<-ch + 1)"),
+            ("!(a || b)", "!(a || b)"),
+            ("(a + b) * c", "((a + b) * c)"),
+            ("^x % y", "(^x % y)"),
+            ("&x", "&x"),
+        ];
+        for (src, want) in table {
+            assert_eq!(show(&expr(src)), want, "{}", src);
+        }
+    }
+
+    #[test]
+    fn primary_exprs() {
+        let table = [
+            ("f()", "f()"),
+            ("f(a, b + 1)", "f(a, (b + 1))"),
+            ("f(xs...)", "f(xs...)"),
+            ("x.y.z", "x.y.z"),
+            ("a[1]", "a[1]"),
+            ("a[i+1]", "a[(i + 1)]"),
+            ("m[\"k\"]", "m[\"k\"]"),
+            ("s[1:2]", "s[1:2]"),
+            ("s[:]", "s[:]"),
+            ("s[:n]", "s[:n]"),
+            ("s[1:2:3]", "s[1:2:3]"),
+            ("x.(T)", "x.(T)"),
+            ("x.(*pkg.T)", "x.(*pkg.T)"),
+            ("f(x).y[0](z)", "f(x).y[0](z)"),
+            ("make([]int, 3)", "make([]int, 3)"),
+            ("new(T)", "new(T)"),
+            ("[]byte(s)", "[]byte(s)"),
+            ("append(xs, []int{1, 2}...)", "append(xs, []int{2}...)"),
+            ("g([]int{1, 2}...)", "g([]int{2}...)"),
+            ("\"a\" + 'b'", "(\"a\" + b)"),
+        ];
+        for (src, want) in table {
+            assert_eq!(show(&expr(src)), want, "{}", src);
+        }
+    }
+    #[test]
+    fn bad_arguments() {
+        let (_, errors) = parse("package p\nfunc f() { g(a..., b) }\n");
+        assert_eq!(errors, vec!["can only use ... with final argument in list"]);
+        let (_, errors) = parse("package p\nfunc f() { g([]int{1}..., b) }\n");
+        assert_eq!(errors, vec!["can only use ... with final argument in list"]);
+    }
+
+    #[test]
+    fn for_loops() {
+        match stmt("for {}") {
+            Statement::For(ForStmt {
+                header:
+                    ForHeader::ForClause(ForClause {
+                        init: None,
+                        condition: None,
+                        post: None,
+                    }),
+                ..
+            }) => {}
+            s => panic!("{:?}", s),
+        }
+        match stmt("for x < 3 {}") {
+            Statement::For(ForStmt {
+                header: ForHeader::Condition(cond),
+                ..
+            }) => {
+                assert_eq!(show(&cond.item), "(x < 3)")
+            }
+            s => panic!("{:?}", s),
+        }
+        match stmt("for i := 0; i < n; i++ {}") {
+            Statement::For(ForStmt {
+                header: ForHeader::ForClause(c),
+                ..
+            }) => {
+                assert!(matches!(c.init, Some(SimpleStmt::ShortVarDecl(_))));
+                assert_eq!(show(&c.condition.unwrap().item), "(i < n)");
+                assert!(matches!(c.post, Some(SimpleStmt::IncDec(_))));
+            }
+            s => panic!("{:?}", s),
+        }
+        match stmt("for ; ; {}") {
+            Statement::For(ForStmt {
+                header: ForHeader::ForClause(c),
+                ..
+            }) => {
+                assert_eq!(
+                    c,
+                    ForClause {
+                        init: None,
+                        condition: None,
+                        post: None
+                    }
+                )
+            }
+            s => panic!("{:?}", s),
+        }
+        match stmt("for k, v := range m {}") {
+            Statement::For(ForStmt {
+                header: ForHeader::Range(r),
+                ..
+            }) => {
+                match r.vars {
+                    Some(IterVars::Idents(names)) => assert_eq!(names.len(), 2),
+                    vars => panic!("{:?}", vars),
+                }
+                assert_eq!(show(&r.expr.item), "m");
+            }
+            s => panic!("{:?}", s),
+        }
+        match stmt("for a[0] = range xs {}") {
+            Statement::For(ForStmt {
+                header: ForHeader::Range(r),
+                ..
+            }) => {
+                assert!(matches!(r.vars, Some(IterVars::Exprs(_))))
+            }
+            s => panic!("{:?}", s),
+        }
+        match stmt("for range ch {}") {
+            Statement::For(ForStmt {
+                header: ForHeader::Range(r),
+                ..
+            }) => {
+                assert_eq!(r.vars, None)
+            }
+            s => panic!("{:?}", s),
+        }
+        // a composite literal in the header has to be in parentheses
+        match stmt("for _, x := range ([]int{1, 2}) {}") {
+            Statement::For(ForStmt {
+                header: ForHeader::Range(r),
+                ..
+            }) => {
+                assert_eq!(show(&r.expr.item), "[]int{2}")
+            }
+            s => panic!("{:?}", s),
+        }
+    }
+
+    #[test]
+    fn if_else_chains() {
+        match stmt("if x := f(); x > 0 {} else if x < 0 { g() } else { h() }") {
+            Statement::If(i) => {
+                assert!(matches!(i.init, Some(SimpleStmt::ShortVarDecl(_))));
+                assert_eq!(show(&i.cond.item), "(x > 0)");
+                match *i.els.unwrap() {
+                    Else::If(inner) => {
+                        assert_eq!(show(&inner.cond.item), "(x < 0)");
+                        assert!(matches!(*inner.els.unwrap(), Else::Block(_)));
+                    }
+                    els => panic!("{:?}", els),
+                }
+            }
+            s => panic!("{:?}", s),
+        }
+        let (_, errors) = parse("package p\nfunc f() { if x {} else y }\n");
+        assert_eq!(errors.len(), 1);
+    }
+    #[test]
+    fn switches() {
+        match stmt("switch x := 1; x { case 1, 2: fallthrough\ncase 3:\ndefault: }") {
+            Statement::Switch(s) => {
+                assert!(s.init.is_some());
+                assert_eq!(show(&s.tag.unwrap().item), "x");
+                let cases: Vec<Option<usize>> = s
+                    .clauses
+                    .iter()
+                    .map(|c| c.case.as_ref().map(Vec::len))
+                    .collect();
+                assert_eq!(cases, vec![Some(2), Some(1), None]);
+                assert!(matches!(
+                    s.clauses[0].body[0].item,
+                    Statement::Fallthrough(_)
+                ));
+            }
+            s => panic!("{:?}", s),
+        }
+        match stmt("switch { case x > 1: }") {
+            Statement::Switch(s) => assert_eq!(s.tag, None),
+            s => panic!("{:?}", s),
+        }
+        match stmt("switch v := y.(type) { case int, []string: case nil: default: }") {
+            Statement::TypeSwitch(s) => {
+                assert_eq!(s.binding.unwrap().item.as_str(), "v");
+                assert_eq!(show_primary(&s.expr.item), "y");
+                let first: Vec<String> = s.clauses[0]
+                    .case
+                    .as_ref()
+                    .unwrap()
+                    .iter()
+                    .map(show_type)
+                    .collect();
+                assert_eq!(first, vec!["int", "[]string"]);
+                assert_eq!(s.clauses.len(), 3);
+            }
+            s => panic!("{:?}", s),
+        }
+        match stmt("switch x := 0; y.(type) {}") {
+            Statement::TypeSwitch(s) => {
+                assert!(s.init.is_some());
+                assert_eq!(s.binding, None);
+            }
+            s => panic!("{:?}", s),
+        }
+    }
+    #[test]
+    fn type_guard_outside_switch() {
+        for body in [
+            "y := x.(type)",
+            "f(x.(type))",
+            "switch x.(type) + 1 {}",
+            "switch { case x.(type): }",
+            "switch -x.(type) {}",
+            "switch a + x.(type) {}",
+            "switch (x.(type)) {}",
+            "switch x.(type); y {}",
+            "switch x := y.(type); z {}",
+            "switch f(func() { if x.(type) {} }) {}",
+        ] {
+            let (_, errors) = parse(&format!("package p\nfunc f() {{ {} }}\n", body));
+            assert_eq!(
+                errors,
+                vec!["use of .(type) outside type switch"],
+                "{}",
+                body
+            );
+        }
+    }
+
+    #[test]
+    fn type_guard_position() {
+        // the inner guard is the bad one, the outer one is the tag
+        let src = "package p\nfunc f() { switch x.(type).(type) {} }";
+        let mut parser = Parser::new(Lexer::new(src.to_string()));
+        parser.parse();
+        let errors: Vec<(String, Span)> = parser
+            .errors
+            .iter()
+            .map(|e| (e.kind.to_string(), e.span))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![(
+                "use of .(type) outside type switch".to_string(),
+                Span { beg: 28, end: 36 }
+            )]
+        );
+        for body in [
+            "switch x := -1; y.(type) {}",
+            "switch v := f(func() {}).(type) {}",
+        ] {
+            let (_, errors) = parse(&format!("package p\nfunc f() {{ {} }}\n", body));
+            assert_eq!(errors, Vec::<String>::new(), "{}", body);
+        }
+    }
+
+    #[test]
+    fn funcs_and_methods() {
+        let decls = decls(
+            "func f(a, b int, s ...string) (int, error) { return 0, nil }
+            func g(int, *T) (n int, err error)
+            func (r *T) M(x int) {}
+            func (T) N() string { return \"\" }",
+        );
+        match &decls[0] {
+            TopLevelDecl::Func(f) => {
+                assert_eq!(f.name.item.as_str(), "f");
+                let params: Vec<(usize, String, bool)> = f
+                    .signature
+                    .params
+                    .iter()
+                    .map(|p| (p.names.len(), show_type(&p.typ), p.variadic))
+                    .collect();
+                assert_eq!(
+                    params,
+                    vec![(2, "int".into(), false), (1, "string".into(), true)]
+                );
+                assert_eq!(f.signature.results.len(), 2);
+                assert!(f.signature.results.iter().all(|r| r.names.is_empty()));
+            }
+            d => panic!("{:?}", d),
+        }
+        match &decls[1] {
+            TopLevelDecl::Func(f) => {
+                assert_eq!(f.body, None);
+                assert_eq!(f.signature.params.len(), 2);
+                assert!(f.signature.params.iter().all(|p| p.names.is_empty()));
+                assert!(f.signature.results.iter().all(|r| r.names.len() == 1));
+            }
+            d => panic!("{:?}", d),
+        }
+        match &decls[2] {
+            TopLevelDecl::Method(m) => {
+                assert_eq!(m.receiver.names[0].item.as_str(), "r");
+                assert_eq!(show_type(&m.receiver.typ), "*T");
+                assert_eq!(m.name.item.as_str(), "M");
+            }
+            d => panic!("{:?}", d),
+        }
+        match &decls[3] {
+            TopLevelDecl::Method(m) => {
+                assert!(m.receiver.names.is_empty());
+                assert_eq!(m.signature.results.len(), 1);
+            }
+            d => panic!("{:?}", d),
+        }
+    }
+    #[test]
+    fn bad_params() {
+        let table = [
+            ("func f(,)", "expected parameter, found Comma"),
+            (
+                "func f(a ...int, b int)",
+                "can only use ... with final parameter in list",
+            ),
+            ("func f(a int, string)", "missing parameter type"),
+        ];
+        for (src, want) in table {
+            let (_, errors) = parse(&format!("package p\n{}\n", src));
+            assert_eq!(errors, vec![want], "{}", src);
+        }
+    }
+
+    #[test]
+    fn types() {
+        let table = [
+            ("int", "int"),
+            ("pkg.T", "pkg.T"),
+            ("[3]int", "[3]int"),
+            ("[N*2]T", "[(N * 2)]T"),
+            ("[]*T", "[]*T"),
+            ("map[string][]int", "map[string][]int"),
+            ("chan int", "chan int"),
+            ("chan<- int", "chan<- int"),
+            ("<-chan int", "<-chan int"),
+            ("chan<- <-chan int", "chan<- <-chan int"),
+            ("chan (<-chan int)", "chan <-chan int"),
+            ("func(int, string) (bool, error)", "func/2/2"),
+            ("func()", "func/0/0"),
+            ("(*T)", "*T"),
+            ("struct{}", "struct/0"),
+            ("interface{}", "interface/0"),
+        ];
+        for (src, want) in table {
+            assert_eq!(show_type(&typ(src)), want, "{}", src);
+        }
+        match typ("struct { x, y int; T; *pkg.U; s string `json:\"s\"` }") {
+            Type::Struct(s) => {
+                let fields: Vec<(usize, String, Option<&str>)> = s
+                    .fields
+                    .iter()
+                    .map(|f| (f.names.len(), show_type(&f.typ), f.tag.map(|t| t.as_str())))
+                    .collect();
+                assert_eq!(
+                    fields,
+                    vec![
+                        (2, "int".into(), None),
+                        (0, "T".into(), None),
+                        (0, "*pkg.U".into(), None),
+                        (1, "string".into(), Some("json:\"s\"")),
+                    ]
+                );
+            }
+            t => panic!("{:?}", t),
+        }
+        match typ("interface { M(int) bool; io.Reader }") {
+            Type::Interface(i) => {
+                assert!(matches!(i.elems[0], InterfaceElem::Method(_)));
+                assert!(matches!(i.elems[1], InterfaceElem::Embedded(_)));
+            }
+            t => panic!("{:?}", t),
+        }
+    }
+
+    #[test]
+    fn grouped_decls_and_iota() {
+        let decls = decls(
+            "const (
+                A = iota * 10
+                B
+                _
+                C, D = 1, 2
+                E, F
+            )
+            var (
+                a = 1
+                b, c int
+            )
+            type (
+                X = Y
+                Z struct{}
+            )",
+        );
+        match &decls[0] {
+            TopLevelDecl::Decl(DeclStmt::Const(c)) => {
+                let specs: Vec<(u32, Vec<String>)> = c
+                    .specs
+                    .iter()
+                    .map(|s| (s.iota, s.values.iter().map(|v| show(&v.item)).collect()))
+                    .collect();
+                let values = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+                assert_eq!(
+                    specs,
+                    vec![
+                        (0, values(&["(iota * 10)"])),
+                        (1, values(&["(iota * 10)"])),
+                        (2, values(&["(iota * 10)"])),
+                        (3, values(&["1", "2"])),
+                        (4, values(&["1", "2"])),
+                    ]
+                );
+            }
+            d => panic!("{:?}", d),
+        }
+        match &decls[1] {
+            TopLevelDecl::Decl(DeclStmt::VarDecl(v)) => {
+                assert_eq!(v.specs.len(), 2);
+                assert_eq!(v.specs[0].typ, None);
+                assert_eq!(v.specs[1].names.len(), 2);
+                assert!(v.specs[1].values.is_empty());
+            }
+            d => panic!("{:?}", d),
+        }
+        match &decls[2] {
+            TopLevelDecl::Decl(DeclStmt::TypeDecl(t)) => {
+                let specs: Vec<(&str, bool)> = t
+                    .specs
+                    .iter()
+                    .map(|s| (s.name.item.as_str(), s.alias))
+                    .collect();
+                assert_eq!(specs, vec![("X", true), ("Z", false)]);
+            }
+            d => panic!("{:?}", d),
+        }
+        // the first spec of a group has nothing to repeat
+        let (_, errors) = parse("package p\nconst (\n\tA\n)\n");
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn literals() {
+        let table = [
+            ("T{}", "T{0}"),
+            ("T{1, 2}", "T{2}"),
+            ("pkg.T{x: 1}", "pkg.T{1}"),
+            ("[]int{1, 2, 3,}", "[]int{3}"),
+            ("[...]string{\"a\"}", "[...]string{1}"),
+            ("map[string]int{\"a\": 1}", "map[string]int{1}"),
+            ("&T{}", "&T{0}"),
+            ("func(x int) int { return x }", "func"),
+            ("func() {}()", "func()"),
+        ];
+        for (src, want) in table {
+            assert_eq!(show(&expr(src)), want, "{}", src);
+        }
+        match primary("[][]int{{1, 2}, {}}") {
+            PrimaryExpr::Operand(Operand::CompositeLit(lit)) => {
+                assert!(lit
+                    .value
+                    .item
+                    .elems
+                    .iter()
+                    .all(|e| matches!(e.value, Element::Lit(_))))
+            }
+            p => panic!("{:?}", p),
+        }
+        match primary("map[P]int{{1, 2}: 3}") {
+            PrimaryExpr::Operand(Operand::CompositeLit(lit)) => {
+                assert!(matches!(lit.value.item.elems[0].key, Some(Element::Lit(_))))
+            }
+            p => panic!("{:?}", p),
+        }
+        // a name and braces in an if header are the condition and the body
+        match stmt("if x == y {}") {
+            Statement::If(i) => assert_eq!(show(&i.cond.item), "(x == y)"),
+            s => panic!("{:?}", s),
+        }
+        match stmt("if x == (T{}) {}") {
+            Statement::If(i) => assert_eq!(show(&i.cond.item), "(x == T{0})"),
+            s => panic!("{:?}", s),
+        }
+    }
+
+    #[test]
+    fn package_and_imports() {
+        let file = parse_ok(
+            "package main
+
+            import \"fmt\"
+            import (
+                f \"fmt\"
+                . \"strings\"
+                _ \"os\"
+            )
+
+            func main() {}",
+        );
+        assert_eq!(file.package.item.as_str(), "main");
+        let imports: Vec<(ImportName, &str)> = file
+            .imports
+            .iter()
+            .map(|i| (i.name, i.path.item.as_str()))
+            .collect();
+        let alias = match imports[1].0 {
+            ImportName::Alias(name) => name,
+            name => panic!("{:?}", name),
+        };
+        assert_eq!(alias.item.as_str(), "f");
+        assert_eq!(
+            imports,
+            vec![
+                (ImportName::Default, "fmt"),
+                (ImportName::Alias(alias), "fmt"),
+                (ImportName::Dot, "strings"),
+                (ImportName::Blank, "os"),
+            ]
+        );
+        assert_eq!(file.decls.len(), 1);
+
+        let (_, errors) = parse("package p\nfunc f() {}\nimport \"fmt\"\n");
+        assert_eq!(
+            errors,
+            vec!["imports must appear before other declarations"]
+        );
+        let (_, errors) = parse("func f() {}\n");
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn error_recovery() {
+        let (file, errors) = parse(
+            "package p
+            func f() {
+                x := )
+                y := 2
+                if {}
+                z := 3
+            }
+            var = 1
+            func g() {}",
+        );
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        let kinds: Vec<&str> = file
+            .decls
+            .iter()
+            .map(|d| match &d.item {
+                TopLevelDecl::Func(_) => "func",
+                TopLevelDecl::Error => "error",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(kinds, vec!["func", "error", "func"]);
+        match &file.decls[0].item {
+            TopLevelDecl::Func(FuncDecl {
+                body: Some(body), ..
+            }) => {
+                let stmts: Vec<&str> = body.stmts.iter().map(|s| stmt_kind(&s.item)).collect();
+                assert_eq!(stmts, vec!["error", "define", "error", "define"]);
+            }
+            d => panic!("{:?}", d),
+        }
+
+        // a broken import doesn't make the ones after it out of place
+        let (file, errors) = parse("package p\nimport 5\nimport \"os\"\n");
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(file.imports.len(), 1);
+        assert!(file.decls.is_empty());
+    }
+
+    #[test]
+    fn generics() {
+        let decls = decls(
+            "func Map[T, U any](xs []T, f func(T) U) []U { return nil }
+            type Number interface { ~int | ~float64 | uint8 }
+            type List[T any] struct { next *List[T] }
+            type Pair[K comparable, V interface{ M() }] struct{}
+            type Set[T ~string | int] map[T]struct{}",
+        );
+        match &decls[0] {
+            TopLevelDecl::Func(f) => {
+                assert_eq!(f.type_params.len(), 1);
+                assert_eq!(f.type_params[0].names.len(), 2);
+                assert_eq!(show_type(&f.type_params[0].constraint[0].typ), "any");
+            }
+            d => panic!("{:?}", d),
+        }
+        match &decls[1] {
+            TopLevelDecl::Decl(DeclStmt::TypeDecl(t)) => match &t.specs[0].typ {
+                Type::Interface(i) => match &i.elems[0] {
+                    InterfaceElem::Union(terms) => {
+                        let terms: Vec<(bool, String)> =
+                            terms.iter().map(|t| (t.tilde, show_type(&t.typ))).collect();
+                        assert_eq!(
+                            terms,
+                            vec![
+                                (true, "int".into()),
+                                (true, "float64".into()),
+                                (false, "uint8".into()),
+                            ]
+                        );
+                    }
+                    e => panic!("{:?}", e),
+                },
+                t => panic!("{:?}", t),
+            },
+            d => panic!("{:?}", d),
+        }
+        match &decls[2] {
+            TopLevelDecl::Decl(DeclStmt::TypeDecl(t)) => match &t.specs[0].typ {
+                Type::Struct(s) => assert_eq!(show_type(&s.fields[0].typ), "*List[T]"),
+                t => panic!("{:?}", t),
+            },
+            d => panic!("{:?}", d),
+        }
+        for decl in &decls[3..] {
+            match decl {
+                TopLevelDecl::Decl(DeclStmt::TypeDecl(t)) => {
+                    assert!(!t.specs[0].type_params.is_empty())
+                }
+                d => panic!("{:?}", d),
+            }
+        }
+
+        let table = [
+            ("F[int](x)", "F[int]?(x)"),
+            ("a[i]", "a[i]?"),
+            ("pkg.F[T]", "pkg.F[T]?"),
+            ("F[int, string](x)", "F[int, string](x)"),
+            ("F[[]int](x)", "F[[]int](x)"),
+            ("F[map[K]V]", "F[map[K]V]"),
+            ("List[int]{}", "List[int]{0}"),
+            ("pkg.Pair[K, V]{}", "pkg.Pair[K, V]{0}"),
+        ];
+        for (src, want) in table {
+            assert_eq!(show(&expr(src)), want, "{}", src);
+        }
+        // the index is still there when it turns out to be a variable
+        match primary("a[i]") {
+            PrimaryExpr::IndexOrInstance(i) => assert_eq!(show(&i.index.item), "i"),
+            p => panic!("{:?}", p),
+        }
+    }
+
+    #[test]
+    fn goroutines_and_channels() {
+        let stmts = stmts(
+            "go f(x)
+            go func() {}()
+            ch <- 1
+            v := <-ch
+            v, ok = <-ch
+            <-ch",
+        );
+        match &stmts[0] {
+            Statement::Go(g) => assert_eq!(show(&g.call.item), "f(x)"),
+            s => panic!("{:?}", s),
+        }
+        match &stmts[2] {
+            Statement::Simple(SimpleStmt::Send(s)) => {
+                assert_eq!(
+                    (show(&s.channel.item), show(&s.value.item)),
+                    ("ch".into(), "1".into())
+                )
+            }
+            s => panic!("{:?}", s),
+        }
+        match &stmts[3] {
+            Statement::Simple(SimpleStmt::ShortVarDecl(d)) => {
+                assert_eq!(show(&d.values[0].item), "<-ch")
+            }
+            s => panic!("{:?}", s),
+        }
+        assert!(matches!(stmts[5], Statement::Simple(SimpleStmt::Expr(_))));
+
+        match stmt(
+            "select {
+            case ch <- 1:
+            case v := <-ch:
+                f(v)
+            case v, ok = <-ch:
+            case <-done:
+            default:
+            }",
+        ) {
+            Statement::Select(s) => {
+                let cases: Vec<String> = s
+                    .clauses
+                    .iter()
+                    .map(|c| match &c.case {
+                        CommCase::Send(_) => "send".to_string(),
+                        CommCase::Recv(r) => match &r.vars {
+                            Some(IterVars::Idents(names)) => format!("recv :={}", names.len()),
+                            Some(IterVars::Exprs(exprs)) => format!("recv ={}", exprs.len()),
+                            None => format!("recv {}", show(&r.recv.item)),
+                        },
+                        CommCase::Default => "default".to_string(),
+                        CommCase::Error => "error".to_string(),
+                    })
+                    .collect();
+                assert_eq!(
+                    cases,
+                    vec!["send", "recv :=1", "recv =2", "recv <-done", "default"]
+                );
+                assert_eq!(s.clauses[1].body.len(), 1);
+            }
+            s => panic!("{:?}", s),
+        }
+
+        let table = [
+            ("go x", "expression in go must be function call"),
+            (
+                "select { case x: }",
+                "select case must be receive, send or assign recv",
+            ),
+        ];
+        for (body, want) in table {
+            let (_, errors) = parse(&format!("package p\nfunc f() {{ {} }}\n", body));
+            assert_eq!(errors, vec![want], "{}", body);
+        }
+    }
+
+    #[test]
+    fn bad_select_case_is_kept() {
+        let (file, errors) =
+            parse("package p\nfunc f() {\n\tselect {\n\tcase x:\n\t\tg()\n\tdefault:\n\t}\n}\n");
+        assert_eq!(
+            errors,
+            vec!["select case must be receive, send or assign recv"]
+        );
+        let stmt = match &file.decls[0].item {
+            TopLevelDecl::Func(FuncDecl {
+                body: Some(body), ..
+            }) => &body.stmts[0].item,
+            d => panic!("{:?}", d),
+        };
+        match stmt {
+            Statement::Select(s) => {
+                assert_eq!(s.clauses.len(), 2);
+                assert_eq!(s.clauses[0].case, CommCase::Error);
+                assert_eq!(s.clauses[0].body.len(), 1);
+                assert_eq!(s.clauses[1].case, CommCase::Default);
+            }
+            s => panic!("{:?}", s),
+        }
+    }
 }
@@ -0,0 +1,192 @@
+use crate::lexer::Span;
+use std::fmt;
+use std::fs;
+use std::io;
+
+/*
+* Every file we load gets registered in the SourceMap and handed a FileId. Files are
+* laid out one after another in a single address space, so a Span is still just two
+* u32 offsets, but we can always work out which file (and line/column in that file)
+* an offset belongs to. This is the same trick rustc uses, and it means tokens and AST
+* nodes don't have to carry a file around with them.
+*/
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct FileId(pub u32);
+
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub id: FileId,
+    pub name: String,
+    pub src: String,
+    // byte offset of the first character of this file in the SourceMap
+    pub start_pos: u32,
+    // byte offset (relative to start_pos) of the first character of every line
+    pub line_starts: Vec<u32>,
+    // offsets (relative to start_pos) of U+FFFD characters that replaced
+    // invalid UTF-8 when the file was read, the lexer reports these
+    pub invalid_utf8: Vec<u32>,
+}
+
+// 1-based line and column, column counts characters rather than bytes
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct LineCol {
+    pub line: u32,
+    pub col: u32,
+}
+
+// A Span resolved to the file it came from
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SpanLocation {
+    pub file: FileId,
+    pub name: String,
+    pub beg: LineCol,
+    pub end: LineCol,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceFile {
+    pub fn new(id: FileId, name: String, src: String, start_pos: u32) -> SourceFile {
+        let mut line_starts = vec![0];
+        for (i, byte) in src.bytes().enumerate() {
+            if byte == b'\n' {
+                line_starts.push(i as u32 + 1);
+            }
+        }
+        SourceFile {
+            id,
+            name,
+            start_pos,
+            line_starts,
+            src,
+            invalid_utf8: Vec::new(),
+        }
+    }
+    // one past the last offset that belongs to this file, the EOF token sits here
+    pub fn end_pos(&self) -> u32 {
+        self.start_pos + self.src.len() as u32
+    }
+    pub fn contains(&self, pos: u32) -> bool {
+        self.start_pos <= pos && pos <= self.end_pos()
+    }
+    // the source text a span covers, the span has to be inside this file
+    pub fn text(&self, span: Span) -> &str {
+        &self.src[(span.beg - self.start_pos) as usize..(span.end - self.start_pos) as usize]
+    }
+    pub fn line_col(&self, pos: u32) -> LineCol {
+        let rel = pos - self.start_pos;
+        let line = match self.line_starts.binary_search(&rel) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        // spans are in bytes but columns count characters
+        let line_start = self.line_starts[line] as usize;
+        let col = match self.src.get(line_start..rel as usize) {
+            Some(text) => text.chars().count() as u32,
+            // not on a character boundary, fall back to the byte count
+            None => rel - line_start as u32,
+        };
+        LineCol {
+            line: line as u32 + 1,
+            col: col + 1,
+        }
+    }
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap { files: Vec::new() }
+    }
+    pub fn add_file(&mut self, name: String, src: String) -> FileId {
+        self.add_file_with_invalid(name, src, Vec::new())
+    }
+    // Source files are supposed to be UTF-8, but we would rather have the lexer
+    // point at the bad bytes than refuse to read the file at all.
+    pub fn add_bytes(&mut self, name: String, bytes: &[u8]) -> FileId {
+        let (src, invalid) = decode_utf8(bytes);
+        self.add_file_with_invalid(name, src, invalid)
+    }
+    fn add_file_with_invalid(&mut self, name: String, src: String, invalid: Vec<u32>) -> FileId {
+        // leave a gap of one after each file so an EOF span of one file
+        // never lands on the first character of the next
+        let start_pos = match self.files.last() {
+            Some(file) => file.end_pos() + 1,
+            None => 0,
+        };
+        let id = FileId(self.files.len() as u32);
+        let mut file = SourceFile::new(id, name, src, start_pos);
+        file.invalid_utf8 = invalid;
+        self.files.push(file);
+        id
+    }
+    pub fn load_file(&mut self, path: &str) -> io::Result<FileId> {
+        let bytes = fs::read(path)?;
+        Ok(self.add_bytes(path.to_string(), &bytes))
+    }
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0 as usize]
+    }
+    pub fn lookup_file(&self, pos: u32) -> Option<&SourceFile> {
+        let idx = match self.files.binary_search_by_key(&pos, |f| f.start_pos) {
+            Ok(idx) => idx,
+            Err(0) => return None,
+            Err(idx) => idx - 1,
+        };
+        self.files.get(idx).filter(|f| f.contains(pos))
+    }
+    pub fn location(&self, span: Span) -> Option<SpanLocation> {
+        let file = self.lookup_file(span.beg)?;
+        // clamp so a bogus span can't run off into the next file
+        let end = span.end.clamp(span.beg, file.end_pos());
+        Some(SpanLocation {
+            file: file.id,
+            name: file.name.clone(),
+            beg: file.line_col(span.beg),
+            end: file.line_col(end),
+        })
+    }
+}
+
+// Lossy UTF-8 decoding that also returns the byte offset (in the decoded
+// string) of every U+FFFD we had to substitute for an invalid sequence.
+fn decode_utf8(mut bytes: &[u8]) -> (String, Vec<u32>) {
+    let mut src = String::with_capacity(bytes.len());
+    let mut invalid = Vec::new();
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(rest) => {
+                src.push_str(rest);
+                return (src, invalid);
+            }
+            Err(err) => {
+                let (valid, rest) = bytes.split_at(err.valid_up_to());
+                // valid_up_to guarantees this part decodes
+                let valid = std::str::from_utf8(valid).unwrap();
+                src.push_str(valid);
+                invalid.push(src.len() as u32);
+                src.push(char::REPLACEMENT_CHARACTER);
+                let skip = err.error_len().unwrap_or(rest.len());
+                bytes = &rest[skip..];
+            }
+        }
+    }
+}
+
+impl fmt::Display for LineCol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+impl fmt::Display for SpanLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.name, self.beg)?;
+        if self.beg != self.end {
+            write!(f, "-{}", self.end)?;
+        }
+        Ok(())
+    }
+}